@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pngme::Chunk;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(chunk) = Chunk::try_from(data) {
+        let _ = chunk.data_as_string();
+        let _ = chunk.to_string();
+        assert_eq!(chunk.as_bytes(), &data[..chunk.as_bytes().len()]);
+    }
+});
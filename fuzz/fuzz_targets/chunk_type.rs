@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pngme::ChunkType;
+use std::str::FromStr;
+
+fuzz_target!(|data: &str| {
+    if let Ok(chunk_type) = ChunkType::from_str(data) {
+        let _ = chunk_type.to_string();
+        let _ = chunk_type.is_critical();
+        let _ = chunk_type.is_public();
+    }
+});
@@ -0,0 +1,13 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use pngme::Png;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(png) = Png::try_from(data) {
+        let _ = png.pixels();
+        let _ = png.as_bytes();
+    }
+    let _ = Png::split_trailing(data);
+    let _ = pngme::audit::audit(data);
+});
@@ -0,0 +1,409 @@
+use crate::pixels::Pixels;
+use crate::progress::{Observer, Progress};
+use crate::Error;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+/// Estimated embedding rate above which an image is reported as carrying data.
+const STEGO_RATE: f64 = 0.1;
+/// Estimated embedding rate above which an image is reported as suspicious.
+const SUSPICIOUS_RATE: f64 = 0.03;
+/// Chi-square attack probability above which a channel counts as carrying data.
+const STEGO_CHI_SQUARE: f64 = 0.95;
+
+/// RS analysis mask applied to groups of four neighbouring samples.
+const RS_MASK: [bool; 4] = [false, true, true, false];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Verdict {
+    Clean,
+    Suspicious,
+    Stego,
+}
+
+/// Steganalysis results for a single colour channel.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct ChannelReport {
+    pub channel: usize,
+    /// Probability of embedding from the Westfeld-Pfitzmann chi-square attack.
+    pub chi_square: f64,
+    /// Embedding rate estimated by RS analysis.
+    pub rs_rate: f64,
+    /// Embedding rate estimated by sample pair analysis.
+    pub spa_rate: f64,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct AnalysisReport {
+    pub channels: Vec<ChannelReport>,
+    /// Mean of the RS and SPA estimates over all analysed channels.
+    pub embedding_rate: f64,
+    pub verdict: Verdict,
+}
+
+impl Display for AnalysisReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "channel  chi-square  rs-rate  spa-rate")?;
+        for c in &self.channels {
+            writeln!(
+                f,
+                "{:>7}  {:>10.4}  {:>7.4}  {:>8.4}",
+                c.channel, c.chi_square, c.rs_rate, c.spa_rate
+            )?;
+        }
+        writeln!(f, "estimated embedding rate: {:.4}", self.embedding_rate)?;
+        write!(f, "verdict: {:?}", self.verdict)
+    }
+}
+
+/// Runs the chi-square attack, RS analysis and sample pair analysis on every
+/// non-alpha channel of `pixels`.
+pub fn analyze(pixels: &Pixels) -> Result<AnalysisReport, Error> {
+    analyze_with_progress(pixels, &mut |_| Ok(()))
+}
+
+/// Like [`analyze`], reporting each channel as it is finished.
+pub fn analyze_with_progress(pixels: &Pixels, progress: Observer) -> Result<AnalysisReport, Error> {
+    if pixels.is_palette() {
+        return Err(Error::Unsupported(
+            "palette images cannot be analysed".to_string(),
+        ));
+    }
+
+    let carriers: Vec<usize> = (0..pixels.channels())
+        .filter(|&c| !pixels.is_alpha_sample(c))
+        .collect();
+    let mut channels = vec![];
+    for &c in &carriers {
+        let rows = channel_rows(pixels, c);
+        channels.push(ChannelReport {
+            channel: c,
+            chi_square: chi_square(&rows, pixels.max_sample()),
+            rs_rate: rs_analysis(&rows),
+            spa_rate: sample_pair_analysis(&rows),
+        });
+        progress(Progress::Analyzing {
+            channels_analyzed: channels.len(),
+            channels_total: carriers.len(),
+        })?;
+    }
+
+    let embedding_rate = channels
+        .iter()
+        .map(|c| (c.rs_rate + c.spa_rate) / 2.0)
+        .sum::<f64>()
+        / channels.len() as f64;
+    // Smooth histograms fool the chi-square attack on clean images, so it
+    // only corroborates a suspicious RS/SPA estimate.
+    let chi_square_hit = channels.iter().all(|c| c.chi_square >= STEGO_CHI_SQUARE);
+    let verdict =
+        if embedding_rate >= STEGO_RATE || (embedding_rate >= SUSPICIOUS_RATE && chi_square_hit) {
+            Verdict::Stego
+        } else if embedding_rate >= SUSPICIOUS_RATE {
+            Verdict::Suspicious
+        } else {
+            Verdict::Clean
+        };
+
+    Ok(AnalysisReport {
+        channels,
+        embedding_rate,
+        verdict,
+    })
+}
+
+fn channel_rows(pixels: &Pixels, channel: usize) -> Vec<Vec<i32>> {
+    let channels = pixels.channels();
+    pixels
+        .samples()
+        .chunks(pixels.width() as usize * channels)
+        .map(|row| {
+            row.iter()
+                .skip(channel)
+                .step_by(channels)
+                .map(|&s| s as i32)
+                .collect()
+        })
+        .collect()
+}
+
+/// Chi-square attack on the pairs-of-values histogram. Returns the
+/// probability that the LSBs carry embedded data.
+fn chi_square(rows: &[Vec<i32>], max: u16) -> f64 {
+    let mut histogram = vec![0u64; max as usize + 1];
+    for &sample in rows.iter().flatten() {
+        histogram[sample as usize] += 1;
+    }
+
+    let mut statistic = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected > 0.0 {
+            statistic += (pair[0] as f64 - expected).powi(2) / expected;
+            categories += 1;
+        }
+    }
+    if categories < 2 {
+        return 0.0;
+    }
+    1.0 - lower_regularized_gamma((categories - 1) as f64 / 2.0, statistic / 2.0)
+}
+
+fn flip(x: i32) -> i32 {
+    x ^ 1
+}
+
+fn shifted_flip(x: i32) -> i32 {
+    ((x + 1) ^ 1) - 1
+}
+
+fn smoothness(group: &[i32]) -> i32 {
+    group.windows(2).map(|w| (w[1] - w[0]).abs()).sum()
+}
+
+/// Fractions of regular and singular groups under `flipping` applied on the mask.
+fn regular_singular(rows: &[Vec<i32>], flipping: fn(i32) -> i32) -> (f64, f64) {
+    let (mut regular, mut singular, mut total) = (0u64, 0u64, 0u64);
+    for group in rows.iter().flat_map(|row| row.chunks_exact(RS_MASK.len())) {
+        let flipped: Vec<i32> = group
+            .iter()
+            .zip(RS_MASK)
+            .map(|(&x, masked)| if masked { flipping(x) } else { x })
+            .collect();
+        let before = smoothness(group);
+        let after = smoothness(&flipped);
+        if after > before {
+            regular += 1;
+        } else if after < before {
+            singular += 1;
+        }
+        total += 1;
+    }
+    if total == 0 {
+        return (0.0, 0.0);
+    }
+    (
+        regular as f64 / total as f64,
+        singular as f64 / total as f64,
+    )
+}
+
+/// RS analysis (Fridrich, Goljan and Du).
+fn rs_analysis(rows: &[Vec<i32>]) -> f64 {
+    let inverted: Vec<Vec<i32>> = rows
+        .iter()
+        .map(|row| row.iter().map(|&x| flip(x)).collect())
+        .collect();
+
+    let (rm, sm) = regular_singular(rows, flip);
+    let (rn, sn) = regular_singular(rows, shifted_flip);
+    let (rm1, sm1) = regular_singular(&inverted, flip);
+    let (rn1, sn1) = regular_singular(&inverted, shifted_flip);
+
+    let d0 = rm - sm;
+    let d1 = rm1 - sm1;
+    let dn0 = rn - sn;
+    let dn1 = rn1 - sn1;
+
+    let a = 2.0 * (d1 + d0);
+    let b = dn0 - dn1 - d1 - 3.0 * d0;
+    let c = d0 - dn0;
+    let z = if a.abs() < f64::EPSILON {
+        if b.abs() < f64::EPSILON {
+            return 0.0;
+        }
+        -c / b
+    } else {
+        // A negative discriminant happens close to full embedding; fall
+        // back to the vertex of the parabola.
+        let root = (b * b - 4.0 * a * c).max(0.0).sqrt();
+        let (z1, z2) = ((-b + root) / (2.0 * a), (-b - root) / (2.0 * a));
+        if z1.abs() < z2.abs() {
+            z1
+        } else {
+            z2
+        }
+    };
+    (z / (z - 0.5)).clamp(0.0, 1.0)
+}
+
+/// Sample pair analysis (Dumitrescu, Wu and Wang) over horizontal neighbours.
+fn sample_pair_analysis(rows: &[Vec<i32>]) -> f64 {
+    let (mut x, mut y, mut k, mut n) = (0f64, 0f64, 0f64, 0f64);
+    for pair in rows.iter().flat_map(|row| row.windows(2)) {
+        let (r, s) = (pair[0], pair[1]);
+        if (s % 2 == 0 && r < s) || (s % 2 == 1 && r > s) {
+            x += 1.0;
+        }
+        if (s % 2 == 0 && r > s) || (s % 2 == 1 && r < s) {
+            y += 1.0;
+        }
+        if s / 2 == r / 2 {
+            k += 1.0;
+        }
+        n += 1.0;
+    }
+    if k == 0.0 {
+        return 0.0;
+    }
+
+    let a = 2.0 * k;
+    let b = 2.0 * (2.0 * x - n);
+    let c = y - x;
+    let root = (b * b - 4.0 * a * c).max(0.0).sqrt();
+    let beta = f64::min((-b + root) / (2.0 * a), (-b - root) / (2.0 * a));
+    (2.0 * beta).clamp(0.0, 1.0)
+}
+
+/// Regularized lower incomplete gamma function P(a, x), used for the
+/// chi-square distribution function.
+fn lower_regularized_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let log_prefix = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1.0 {
+        let (mut term, mut sum, mut n) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        (sum * log_prefix.exp()).clamp(0.0, 1.0)
+    } else {
+        // Lentz's continued fraction for the upper function Q(a, x).
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..1000 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        (1.0 - log_prefix.exp() * h).clamp(0.0, 1.0)
+    }
+}
+
+/// Lanczos approximation of ln Γ(x).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.180_091_729_471_46,
+        -86.505_320_329_416_77,
+        24.014_098_240_830_91,
+        -1.231_739_572_450_155,
+        0.120_865_097_386_617_9e-2,
+        -0.539_523_938_495_3e-5,
+    ];
+    let tmp = x + 5.5 - (x + 0.5) * (x + 5.5).ln();
+    let series = COEFFICIENTS
+        .iter()
+        .enumerate()
+        .fold(1.000_000_000_190_015, |sum, (j, c)| {
+            sum + c / (x + 1.0 + j as f64)
+        });
+    -tmp + (2.506_628_274_631_000_5 * series / x).ln()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lsb::{self, PixelMethod};
+
+    /// A smooth synthetic photograph-like cover with mild sensor noise.
+    fn natural_cover() -> Pixels {
+        let (width, height) = (128u32, 128u32);
+        let mut state: u32 = 12345;
+        let mut samples = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..3 {
+                    state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    let noise = ((state >> 16) % 5) as f64 - 2.0;
+                    let base = 128.0
+                        + 60.0 * ((x as f64 + 10.0 * c as f64) / 17.0).sin()
+                        + 40.0 * (y as f64 / 23.0).cos();
+                    samples.push((base + noise).round().clamp(0.0, 255.0) as u16);
+                }
+            }
+        }
+        Pixels::new(width, height, 8, 2, samples).unwrap()
+    }
+
+    fn full_payload(pixels: &Pixels) -> Vec<u8> {
+        let mut state: u32 = 99;
+        (0..lsb::capacity(pixels).unwrap())
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_cover() {
+        let report = analyze(&natural_cover()).unwrap();
+        assert_eq!(report.channels.len(), 3);
+        assert!(report.embedding_rate < SUSPICIOUS_RATE, "{}", report);
+        assert_eq!(report.verdict, Verdict::Clean);
+    }
+
+    #[test]
+    fn test_full_replacement_detected() {
+        let mut pixels = natural_cover();
+        let payload = full_payload(&pixels);
+        lsb::embed(&mut pixels, &payload, "key", PixelMethod::Replacement).unwrap();
+        let report = analyze(&pixels).unwrap();
+        assert!(report.embedding_rate > 0.5, "{}", report);
+        assert!(report
+            .channels
+            .iter()
+            .all(|c| c.chi_square > STEGO_CHI_SQUARE));
+        assert_eq!(report.verdict, Verdict::Stego);
+    }
+
+    #[test]
+    fn test_partial_replacement_estimate() {
+        let mut pixels = natural_cover();
+        let payload = full_payload(&pixels);
+        let half = &payload[..payload.len() / 2];
+        lsb::embed(&mut pixels, half, "key", PixelMethod::Replacement).unwrap();
+        let report = analyze(&pixels).unwrap();
+        assert!((report.embedding_rate - 0.5).abs() < 0.15, "{}", report);
+    }
+
+    #[test]
+    fn test_chi_square_distribution() {
+        // P(1, x) = 1 - e^-x
+        assert!((lower_regularized_gamma(1.0, 2.0) - (1.0 - (-2.0f64).exp())).abs() < 1e-9);
+        assert!((lower_regularized_gamma(3.0, 0.5) - 0.014_387_677_966_970_687).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_palette_rejected() {
+        let pixels = Pixels::new(2, 2, 8, 3, vec![0; 4]).unwrap();
+        assert!(analyze(&pixels).is_err());
+    }
+}
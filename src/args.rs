@@ -1,4 +1,9 @@
 use clap::{Parser, Subcommand};
+use pngme::commands::Output;
+use pngme::lsb::PixelMethod;
+use pngme::png::{Occurrence, Position};
+#[cfg(feature = "server")]
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -13,16 +18,174 @@ pub enum Commands {
         path: String,
         chunk_type: String,
         message: String,
+        /// Where to insert the chunk: auto, end, an index, before:TYPE or after:TYPE
+        #[arg(long, default_value = "auto")]
+        position: Position,
+        /// Write critical, public or reserved chunk types anyway
+        #[arg(long)]
+        force: bool,
+        #[command(flatten)]
+        output: OutputArgs,
     },
     Decode {
         path: String,
         chunk_type: String,
+        /// Which chunks of this type to decode: first, all, N or START..END
+        #[arg(long, default_value = "first")]
+        occurrence: Occurrence,
     },
     Remove {
         path: String,
         chunk_type: String,
+        /// Which chunks of this type to remove: first, all, N or START..END
+        #[arg(long, default_value = "first")]
+        occurrence: Occurrence,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// List every chunk of a type with its index and size
+    List {
+        path: String,
+        chunk_type: String,
     },
     Print {
         path: String,
+        /// Report suspicious chunks instead of listing chunk types
+        #[arg(long)]
+        audit: bool,
+        /// Print the audit report as JSON
+        #[arg(long, requires = "audit")]
+        json: bool,
+    },
+    /// Hide a message in the pixel LSBs, keyed by a stego key
+    Embed {
+        path: String,
+        message: String,
+        #[arg(long)]
+        key: String,
+        #[arg(long, value_enum, default_value_t = PixelMethod::default())]
+        method: PixelMethod,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Recover a message hidden in the pixel LSBs
+    Extract {
+        path: String,
+        #[arg(long)]
+        key: String,
+    },
+    /// Run chi-square, RS and sample pair steganalysis on the pixels
+    Analyze {
+        path: String,
+    },
+    /// Render one bit plane of one channel as a black and white PNG
+    Bitplane {
+        path: String,
+        #[arg(long, default_value_t = 0)]
+        channel: usize,
+        #[arg(long, default_value_t = 0)]
+        bit: u8,
+        /// XOR the plane against the same plane of this image
+        #[arg(long)]
+        diff: Option<String>,
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Strip everything but critical chunks, re-encode the image data and
+    /// write a clean copy
+    Sanitize {
+        path: String,
+        #[arg(short, long)]
+        output: String,
+        /// Ancillary chunk type to keep, may be repeated
+        #[arg(long = "keep")]
+        keep: Vec<String>,
+        /// Replace every pixel LSB with a random bit
+        #[arg(long)]
+        randomize_lsb: bool,
+    },
+    /// Recover chunks from a damaged image and report what was salvaged
+    Repair {
+        path: String,
+        /// Write the recovered image here
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Keep chunks with a bad CRC, recomputing it
+        #[arg(long)]
+        fix_crc: bool,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Read, write or remove data appended after IEND
+    Overlay {
+        #[command(subcommand)]
+        action: OverlayAction,
+    },
+    /// Start the HTTP server
+    #[cfg(feature = "server")]
+    Serve {
+        /// Address to bind, overrides Rocket.toml
+        #[arg(long)]
+        host: Option<String>,
+        /// Port to listen on, overrides Rocket.toml
+        #[arg(long)]
+        port: Option<u16>,
+        /// Directory holding each user's workspace of uploaded images
+        #[arg(long, default_value = "./images")]
+        storage_dir: PathBuf,
+        /// Rocket.toml profile to use, e.g. `release` or `nyc`
+        #[arg(long)]
+        profile: Option<String>,
+    },
+}
+
+/// Where an edit is written; a new file unless in-place is asked for.
+#[derive(clap::Args)]
+pub struct OutputArgs {
+    /// Write the edited image here instead of IMAGE.out.png
+    #[arg(short, long, conflicts_with = "in_place")]
+    output: Option<String>,
+    /// Overwrite the input image
+    #[arg(long)]
+    in_place: bool,
+    /// Keep the original as IMAGE.bak when editing in place
+    #[arg(long, requires = "in_place")]
+    backup: bool,
+}
+
+impl OutputArgs {
+    pub fn resolve(self, path: &str) -> Output {
+        match self.output {
+            _ if self.in_place => Output::InPlace { backup: self.backup },
+            Some(output) => Output::File(output),
+            None => Output::beside(path),
+        }
+    }
+}
+
+#[derive(Subcommand)]
+pub enum OverlayAction {
+    /// Print the overlay, or write it to a file with -o
+    Read {
+        path: String,
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Replace the overlay with a message or the contents of a file
+    Write {
+        path: String,
+        #[arg(long, required_unless_present = "file", conflicts_with = "file")]
+        message: Option<String>,
+        #[arg(long)]
+        file: Option<String>,
+        #[command(flatten)]
+        output: OutputArgs,
+    },
+    /// Strip everything after IEND
+    Remove {
+        path: String,
+        #[command(flatten)]
+        output: OutputArgs,
     },
 }
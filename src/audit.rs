@@ -0,0 +1,419 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+/// Chunk types defined by the PNG specification and its registered extensions.
+pub(crate) const KNOWN_CHUNKS: [&str; 31] = [
+    "IHDR", "PLTE", "IDAT", "IEND", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "bKGD", "hIST", "tRNS",
+    "pHYs", "sPLT", "tIME", "iTXt", "tEXt", "zTXt", "oFFs", "pCAL", "sCAL", "gIFg", "gIFx", "sTER",
+    "dSIG", "eXIf", "acTL", "fcTL", "fdAT", "cICP", "cLLi",
+];
+/// Chunks the image cannot be decoded without, always kept by sanitize and repair.
+pub(crate) const CRITICAL_CHUNKS: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+const TEXT_CHUNKS: [&str; 3] = ["tEXt", "zTXt", "iTXt"];
+/// Ancillary chunks whose payload is compressed by design.
+const COMPRESSED_CHUNKS: [&str; 4] = ["zTXt", "iTXt", "iCCP", "fdAT"];
+
+/// Text chunks larger than this are reported as oversized.
+const TEXT_CHUNK_LIMIT: usize = 8 * 1024;
+/// Payloads shorter than this are too small for a meaningful entropy estimate.
+const ENTROPY_MIN_LENGTH: usize = 64;
+/// Shannon entropy, in bits per byte, above which a payload looks encrypted or compressed.
+const ENTROPY_THRESHOLD: f64 = 7.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct Finding {
+    pub severity: Severity,
+    /// Stable identifier of the check that raised this finding.
+    pub kind: &'static str,
+    /// Byte offset of the chunk (or trailing data) in the file.
+    pub offset: usize,
+    pub chunk_index: Option<usize>,
+    pub chunk_type: Option<String>,
+    pub message: String,
+}
+
+/// Findings of an audit, most severe first.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct AuditReport {
+    pub chunks: usize,
+    pub findings: Vec<Finding>,
+}
+
+impl Display for AuditReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "scanned {} chunks, {} findings",
+            self.chunks,
+            self.findings.len()
+        )?;
+        for finding in &self.findings {
+            write!(
+                f,
+                "\n[{:?}] offset {} {}: {}",
+                finding.severity,
+                finding.offset,
+                finding.chunk_type.as_deref().unwrap_or("-"),
+                finding.message
+            )?;
+        }
+        Ok(())
+    }
+}
+
+struct Auditor {
+    findings: Vec<Finding>,
+}
+
+impl Auditor {
+    fn flag(
+        &mut self,
+        severity: Severity,
+        kind: &'static str,
+        offset: usize,
+        chunk: Option<(usize, &str)>,
+        message: String,
+    ) {
+        self.findings.push(Finding {
+            severity,
+            kind,
+            offset,
+            chunk_index: chunk.map(|(index, _)| index),
+            chunk_type: chunk.map(|(_, name)| name.to_string()),
+            message,
+        });
+    }
+}
+
+/// Walks the raw chunk stream of `bytes` and reports anything that could
+/// hide data or indicates tampering. Unlike `Png::try_from` this never
+/// stops at a bad CRC, so every chunk is inspected.
+pub fn audit(bytes: &[u8]) -> AuditReport {
+    let mut auditor = Auditor { findings: vec![] };
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    let mut chunks = 0;
+
+    if bytes.len() < 8 || bytes[..8] != Png::STANDARD_HEADER {
+        auditor.flag(
+            Severity::High,
+            "invalid_signature",
+            0,
+            None,
+            "file does not start with the PNG signature".to_string(),
+        );
+        return AuditReport {
+            chunks,
+            findings: auditor.findings,
+        };
+    }
+
+    let mut offset = 8;
+    let mut seen_idat = false;
+    let mut previous_idat = false;
+    let mut seen_iend = false;
+
+    while offset < bytes.len() && !seen_iend {
+        let remaining = &bytes[offset..];
+        let length = remaining
+            .get(..4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+        let length = match length {
+            Some(length) if remaining.len() >= 12 && length <= remaining.len() - 12 => length,
+            _ => {
+                auditor.flag(
+                    Severity::High,
+                    "truncated_chunk",
+                    offset,
+                    None,
+                    format!(
+                        "chunk header claims more data than the {} bytes left",
+                        remaining.len()
+                    ),
+                );
+                offset = bytes.len();
+                break;
+            }
+        };
+
+        let type_bytes = [remaining[4], remaining[5], remaining[6], remaining[7]];
+        let name = String::from_utf8_lossy(&type_bytes).to_string();
+        let data = &remaining[8..8 + length];
+        let stored_crc = u32::from_be_bytes([
+            remaining[8 + length],
+            remaining[9 + length],
+            remaining[10 + length],
+            remaining[11 + length],
+        ]);
+        let chunk = Some((chunks, name.as_str()));
+        let name = name.as_str();
+
+        let expected_crc = Chunk::checksum(&type_bytes, data);
+        if expected_crc != stored_crc {
+            auditor.flag(
+                Severity::High,
+                "crc_mismatch",
+                offset,
+                chunk,
+                format!(
+                    "CRC is {:#010x}, expected {:#010x}",
+                    stored_crc, expected_crc
+                ),
+            );
+        }
+
+        if let Ok(chunk_type) = ChunkType::try_from(type_bytes) {
+            let known = KNOWN_CHUNKS.contains(&name);
+            if chunk_type.is_critical() && !CRITICAL_CHUNKS.contains(&name) {
+                auditor.flag(
+                    Severity::High,
+                    "unknown_critical_chunk",
+                    offset,
+                    chunk,
+                    "critical chunk type is not defined by the PNG specification".to_string(),
+                );
+            } else if !chunk_type.is_public() {
+                auditor.flag(
+                    Severity::Medium,
+                    "private_chunk",
+                    offset,
+                    chunk,
+                    format!("non-standard private chunk carrying {} bytes", length),
+                );
+            } else if !known {
+                auditor.flag(
+                    Severity::Low,
+                    "unknown_public_chunk",
+                    offset,
+                    chunk,
+                    "public chunk type is not registered".to_string(),
+                );
+            }
+
+            if !chunk_type.is_critical() {
+                if seen_idat {
+                    auditor.flag(
+                        Severity::Low,
+                        "ancillary_after_idat",
+                        offset,
+                        chunk,
+                        "ancillary chunk placed after the image data".to_string(),
+                    );
+                }
+                if TEXT_CHUNKS.contains(&name) && length > TEXT_CHUNK_LIMIT {
+                    auditor.flag(
+                        Severity::Medium,
+                        "oversized_text_chunk",
+                        offset,
+                        chunk,
+                        format!(
+                            "text chunk of {} bytes exceeds {} bytes",
+                            length, TEXT_CHUNK_LIMIT
+                        ),
+                    );
+                }
+                if !COMPRESSED_CHUNKS.contains(&name) && length >= ENTROPY_MIN_LENGTH {
+                    let entropy = shannon_entropy(data);
+                    if entropy >= ENTROPY_THRESHOLD {
+                        auditor.flag(
+                            Severity::Medium,
+                            "high_entropy_payload",
+                            offset,
+                            chunk,
+                            format!("payload entropy is {:.2} bits per byte", entropy),
+                        );
+                    }
+                }
+            }
+        } else {
+            auditor.flag(
+                Severity::High,
+                "invalid_chunk_type",
+                offset,
+                chunk,
+                "chunk type is not four ASCII letters".to_string(),
+            );
+        }
+
+        let count = counts.entry(name.to_string()).or_insert(0);
+        *count += 1;
+        if *count == 2 && ["IHDR", "PLTE", "IEND"].contains(&name) {
+            auditor.flag(
+                Severity::High,
+                "duplicate_critical_chunk",
+                offset,
+                chunk,
+                format!("{} appears more than once", name),
+            );
+        }
+        if name == "IDAT" && seen_idat && !previous_idat {
+            auditor.flag(
+                Severity::Medium,
+                "split_idat",
+                offset,
+                chunk,
+                "IDAT chunks are not consecutive".to_string(),
+            );
+        }
+
+        previous_idat = name == "IDAT";
+        seen_idat |= previous_idat;
+        seen_iend = name == "IEND";
+        offset += length + 12;
+        chunks += 1;
+    }
+
+    if !seen_iend {
+        auditor.flag(
+            Severity::High,
+            "missing_iend",
+            offset,
+            None,
+            "no IEND chunk found".to_string(),
+        );
+    } else if offset < bytes.len() {
+        auditor.flag(
+            Severity::High,
+            "data_after_iend",
+            offset,
+            None,
+            format!("{} bytes follow the IEND chunk", bytes.len() - offset),
+        );
+    }
+
+    auditor.findings.sort_by_key(|f| Reverse(f.severity));
+    AuditReport {
+        chunks,
+        findings: auditor.findings,
+    }
+}
+
+fn shannon_entropy(data: &[u8]) -> f64 {
+    let mut histogram = [0usize; 256];
+    for &byte in data {
+        histogram[byte as usize] += 1;
+    }
+    let total = data.len() as f64;
+    histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::Pixels;
+    use std::str::FromStr;
+
+    fn chunk_bytes(chunk_type: &str, data: &[u8]) -> Vec<u8> {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.to_vec()).as_bytes()
+    }
+
+    /// A valid image with `extra` chunks inserted before IEND.
+    fn image_with(extra: &[Vec<u8>]) -> Vec<u8> {
+        let pixels = Pixels::new(4, 4, 8, 0, vec![7; 16]).unwrap();
+        let mut bytes = Png::from_pixels(&pixels).as_bytes();
+        let iend = bytes.split_off(bytes.len() - 12);
+        for chunk in extra {
+            bytes.extend(chunk);
+        }
+        bytes.extend(iend);
+        bytes
+    }
+
+    fn kinds(report: &AuditReport) -> Vec<&'static str> {
+        report.findings.iter().map(|f| f.kind).collect()
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let report = audit(&image_with(&[]));
+        assert_eq!(report.chunks, 3);
+        assert!(report.findings.is_empty());
+    }
+
+    #[test]
+    fn test_private_chunk_after_idat() {
+        let report = audit(&image_with(&[chunk_bytes("ruSt", b"secret")]));
+        assert_eq!(
+            kinds(&report),
+            vec!["private_chunk", "ancillary_after_idat"]
+        );
+        assert_eq!(report.findings[0].chunk_type.as_deref(), Some("ruSt"));
+    }
+
+    #[test]
+    fn test_data_after_iend() {
+        let mut bytes = image_with(&[]);
+        bytes.extend(b"hidden");
+        let report = audit(&bytes);
+        assert_eq!(kinds(&report), vec!["data_after_iend"]);
+        assert!(report.findings[0].message.starts_with("6 bytes"));
+    }
+
+    #[test]
+    fn test_crc_mismatch() {
+        let mut chunk = chunk_bytes("tEXt", b"Comment\0hello");
+        let last = chunk.len() - 1;
+        chunk[last] ^= 0xFF;
+        let report = audit(&image_with(&[chunk]));
+        assert_eq!(report.findings[0].kind, "crc_mismatch");
+        assert_eq!(report.findings[0].severity, Severity::High);
+    }
+
+    #[test]
+    fn test_oversized_text_and_duplicate_critical() {
+        let text = vec![b'a'; TEXT_CHUNK_LIMIT + 1];
+        let report = audit(&image_with(&[
+            chunk_bytes("tEXt", &text),
+            chunk_bytes("IHDR", &[0; 13]),
+        ]));
+        let kinds = kinds(&report);
+        assert_eq!(kinds[0], "duplicate_critical_chunk");
+        assert!(kinds.contains(&"oversized_text_chunk"));
+    }
+
+    #[test]
+    fn test_high_entropy_payload() {
+        let mut state: u32 = 1;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let report = audit(&image_with(&[chunk_bytes("tEXt", &noise)]));
+        assert!(kinds(&report).contains(&"high_entropy_payload"));
+    }
+
+    #[test]
+    fn test_truncated_and_invalid_signature() {
+        let mut bytes = image_with(&[]);
+        bytes.truncate(bytes.len() - 4);
+        let report = audit(&bytes);
+        assert_eq!(kinds(&report), vec!["truncated_chunk", "missing_iend"]);
+
+        let report = audit(b"not a png");
+        assert_eq!(kinds(&report), vec!["invalid_signature"]);
+    }
+}
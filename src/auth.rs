@@ -0,0 +1,383 @@
+use pngme::storage::{check_workspace_name, Expired, Storage};
+use pngme::Error;
+use rocket::data::ByteUnit;
+use rocket::fairing::{AdHoc, Fairing, Info, Kind};
+use rocket::figment::providers::{Format, Toml};
+use rocket::figment::Figment;
+use rocket::http::{Header, Method, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::serde::{Deserialize, Serialize};
+use rocket::{Request, Response};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// What a token may do. `write` covers everything `read` does.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Inspect stored images: print, decode, list, download and the analyses
+    Read,
+    /// Create or change images: upload, encode, remove, embed and the like
+    Write,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenConfig {
+    pub token: String,
+    /// Whose workspace the token opens; several tokens may share one
+    pub user: String,
+    pub scopes: Vec<Scope>,
+}
+
+/// Storage limits for a workspace; unset fields fall back to the defaults
+/// in the `auth` table.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+pub struct Limits {
+    pub quota: Option<ByteUnit>,
+    /// Seconds a file is kept after its last write
+    pub retention_secs: Option<u64>,
+}
+
+/// The `auth` table of Rocket.toml.
+#[derive(Debug, Deserialize)]
+pub struct AuthConfig {
+    #[serde(default)]
+    pub tokens: Vec<TokenConfig>,
+    /// A TOML file with more `[[tokens]]`, kept out of version control
+    #[serde(default)]
+    pub tokens_file: Option<PathBuf>,
+    /// Origins allowed to call the API from a browser; `*` allows any
+    #[serde(default)]
+    pub cors_origins: Vec<String>,
+    #[serde(flatten)]
+    pub limits: Limits,
+    /// Limits for individual users, by user name
+    #[serde(default)]
+    pub users: HashMap<String, Limits>,
+    /// Seconds between sweeps for expired files
+    #[serde(default = "default_cleanup_interval")]
+    pub cleanup_interval_secs: u64,
+}
+
+impl Default for AuthConfig {
+    fn default() -> AuthConfig {
+        AuthConfig {
+            tokens: vec![],
+            tokens_file: None,
+            cors_origins: vec![],
+            limits: Limits::default(),
+            users: HashMap::new(),
+            cleanup_interval_secs: default_cleanup_interval(),
+        }
+    }
+}
+
+fn default_cleanup_interval() -> u64 {
+    300
+}
+
+#[derive(Deserialize)]
+struct TokensFile {
+    #[serde(default)]
+    tokens: Vec<TokenConfig>,
+}
+
+/// The tokens the server accepts and the limits of each user's workspace,
+/// loaded once at ignition.
+pub struct Tokens {
+    tokens: Vec<TokenConfig>,
+    limits: Limits,
+    users: HashMap<String, Limits>,
+    cleanup_interval: Duration,
+}
+
+impl Tokens {
+    /// Reads the `auth` table and any tokens file it names.
+    pub fn load(figment: &Figment) -> Result<(Tokens, Vec<String>), Box<dyn std::error::Error>> {
+        let config: AuthConfig = match figment.find_value("auth") {
+            Ok(_) => figment.extract_inner("auth")?,
+            Err(_) => AuthConfig::default(),
+        };
+        let mut tokens = config.tokens;
+        match config.tokens_file {
+            Some(path) if path.exists() => {
+                let file: TokensFile = Figment::from(Toml::file_exact(&path)).extract()?;
+                tokens.extend(file.tokens);
+            }
+            Some(path) => eprintln!("Tokens file {} not found, skipping it", path.display()),
+            None => {}
+        }
+        if let Some(empty) = tokens.iter().find(|t| t.token.is_empty()) {
+            return Err(format!("empty token for user {}", empty.user).into());
+        }
+        for user in tokens.iter().map(|t| &t.user).chain(config.users.keys()) {
+            check_workspace_name(user)?;
+        }
+        let tokens = Tokens {
+            tokens,
+            limits: config.limits,
+            users: config.users,
+            cleanup_interval: Duration::from_secs(config.cleanup_interval_secs.max(1)),
+        };
+        Ok((tokens, config.cors_origins))
+    }
+
+    /// `user`'s workspace under `storage` with its limits applied. Expired
+    /// files are left to [`Tokens::sweep`]; the workspace already refuses to
+    /// serve them.
+    fn workspace(&self, storage: &Storage, user: &str) -> Result<Storage, Error> {
+        let limits = self.users.get(user).copied().unwrap_or_default();
+        let quota = limits.quota.or(self.limits.quota).map(|quota| quota.as_u64());
+        let retention = limits.retention_secs.or(self.limits.retention_secs);
+        Ok(storage
+            .workspace(user)?
+            .with_quota(quota)
+            .with_retention(retention.map(Duration::from_secs)))
+    }
+
+    /// Deletes expired files from every workspace under `storage`.
+    pub fn sweep(&self, storage: &Storage) -> Result<Expired, Error> {
+        let mut expired = Expired::default();
+        for user in storage.workspaces()? {
+            expired.merge(self.workspace(storage, &user)?.expire()?);
+        }
+        Ok(expired)
+    }
+
+    /// How often the background task calls [`Tokens::sweep`].
+    pub fn cleanup_interval(&self) -> Duration {
+        self.cleanup_interval
+    }
+
+    /// The configuration of `token`. Every configured token is compared so
+    /// the time taken does not reveal how close a guess was.
+    fn find(&self, token: &str) -> Option<&TokenConfig> {
+        self.tokens.iter().fold(None, |found, config| {
+            let matches = constant_time_eq(config.token.as_bytes(), token.as_bytes());
+            if matches && found.is_none() {
+                Some(config)
+            } else {
+                found
+            }
+        })
+    }
+
+    fn grants(&self, token: &str, scope: Scope) -> Option<bool> {
+        self.find(token)
+            .map(|config| config.scopes.contains(&scope) || config.scopes.contains(&Scope::Write))
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// Origins allowed to make cross-origin requests.
+pub struct AllowedOrigins(Vec<String>);
+
+/// Loads [`Tokens`] and the CORS allow-list from the configuration,
+/// refusing to launch if either is malformed. A missing tokens file only
+/// means fewer tokens.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Authentication", |rocket| async move {
+        match Tokens::load(rocket.figment()) {
+            Ok((tokens, origins)) => {
+                if tokens.tokens.is_empty() {
+                    eprintln!("No API tokens configured; every protected endpoint will answer 401");
+                }
+                Ok(rocket.manage(Arc::new(tokens)).manage(AllowedOrigins(origins)))
+            }
+            Err(e) => {
+                eprintln!("Invalid auth configuration: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+// Why a request was refused, for the 401 and 403 catchers to report.
+struct Refusal(String);
+
+/// The reason the guards gave for refusing `request`.
+pub fn refusal(request: &Request<'_>) -> String {
+    request
+        .local_cache(|| Refusal("authentication required".to_string()))
+        .0
+        .clone()
+}
+
+fn authorize(request: &Request<'_>, scope: Scope) -> Outcome<Storage, Error> {
+    let outcome = check(request, scope);
+    if let Outcome::Error((_, error)) = &outcome {
+        request.local_cache(|| Refusal(error.to_string()));
+    }
+    outcome
+}
+
+fn check(request: &Request<'_>, scope: Scope) -> Outcome<Storage, Error> {
+    let token = request
+        .headers()
+        .get_one("Authorization")
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let Some(token) = token else {
+        let error = Error::AuthFailure("missing bearer token".to_string());
+        return Outcome::Error((Status::Unauthorized, error));
+    };
+    let Some(tokens) = request.rocket().state::<Arc<Tokens>>() else {
+        let error = Error::AuthFailure("no tokens loaded".to_string());
+        return Outcome::Error((Status::InternalServerError, error));
+    };
+    match tokens.grants(token, scope) {
+        Some(true) => {
+            let user = &tokens.find(token).expect("granted tokens exist").user;
+            let workspace = request
+                .rocket()
+                .state::<Storage>()
+                .ok_or_else(|| Error::InvalidArgument("no storage configured".to_string()))
+                .and_then(|storage| tokens.workspace(storage, user));
+            match workspace {
+                Ok(workspace) => Outcome::Success(workspace),
+                Err(e) => Outcome::Error((Status::InternalServerError, e)),
+            }
+        }
+        Some(false) => {
+            let error = Error::AuthFailure(format!("token lacks the {:?} scope", scope));
+            Outcome::Error((Status::Forbidden, error))
+        }
+        None => {
+            let error = Error::AuthFailure("unknown token".to_string());
+            Outcome::Error((Status::Unauthorized, error))
+        }
+    }
+}
+
+/// Request guard for endpoints that only inspect images, holding the
+/// caller's workspace.
+pub struct ReadAccess {
+    pub workspace: Storage,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ReadAccess {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Scope::Read).map(|workspace| ReadAccess { workspace })
+    }
+}
+
+/// Request guard for endpoints that create or change images, holding the
+/// caller's workspace.
+pub struct WriteAccess {
+    pub workspace: Storage,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WriteAccess {
+    type Error = Error;
+
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        authorize(request, Scope::Write).map(|workspace| WriteAccess { workspace })
+    }
+}
+
+/// Answers cross-origin requests only for the configured origins.
+pub struct Cors;
+
+#[rocket::async_trait]
+impl Fairing for Cors {
+    fn info(&self) -> Info {
+        Info {
+            name: "Cross-Origin-Resource-Sharing Fairing",
+            kind: Kind::Response,
+        }
+    }
+
+    async fn on_response<'r>(&self, request: &'r Request<'_>, response: &mut Response<'r>) {
+        let Some(origin) = request.headers().get_one("Origin") else {
+            return;
+        };
+        let allowed = request.rocket().state::<AllowedOrigins>().is_some_and(|origins| {
+            origins.0.iter().any(|allowed| allowed == "*" || allowed == origin)
+        });
+        if !allowed {
+            if request.method() == Method::Options {
+                response.set_status(Status::Forbidden);
+            }
+            return;
+        }
+        response.set_header(Header::new("Access-Control-Allow-Origin", origin.to_string()));
+        response.set_header(Header::new("Vary", "Origin"));
+        response.set_header(Header::new(
+            "Access-Control-Allow-Methods",
+            "POST, PATCH, PUT, DELETE, HEAD, OPTIONS, GET",
+        ));
+        response.set_header(Header::new(
+            "Access-Control-Allow-Headers",
+            "Authorization, Content-Type, Accept, Payload-Key",
+        ));
+        // Scripts only see the headers listed here besides the basic ones.
+        response.set_header(Header::new(
+            "Access-Control-Expose-Headers",
+            "Location, Deprecation, Link",
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tokens_file_and_scopes() {
+        let dir = crate::testing::temp_dir("tokens");
+        let path = dir.path().join("tokens.toml");
+        std::fs::write(&path, "[[tokens]]\ntoken = \"from-file\"\nuser = \"alice\"\nscopes = [\"read\"]\n").unwrap();
+        let config = format!(
+            "[auth]\ntokens_file = {:?}\ncors_origins = [\"*\"]\nquota = \"1 MiB\"\n[[auth.tokens]]\ntoken = \"inline\"\nuser = \"bob\"\nscopes = [\"write\"]\n[auth.users.bob]\nquota = \"2 KiB\"\n",
+            path
+        );
+        let (tokens, origins) = Tokens::load(&Figment::from(Toml::string(&config))).unwrap();
+
+        assert_eq!(origins, ["*"]);
+        assert_eq!(tokens.grants("from-file", Scope::Read), Some(true));
+        assert_eq!(tokens.grants("from-file", Scope::Write), Some(false));
+        assert_eq!(tokens.grants("inline", Scope::Read), Some(true));
+        assert_eq!(tokens.grants("inlin", Scope::Read), None);
+        assert_eq!(tokens.find("inline").unwrap().user, "bob");
+        assert_eq!(tokens.users["bob"].quota, Some(ByteUnit::Kibibyte(2)));
+        assert_eq!(tokens.limits.quota, Some(ByteUnit::Mebibyte(1)));
+
+        let missing = Tokens::load(&Figment::from(Toml::string("[auth]\ntokens_file = \"/nonexistent\"\n")));
+        assert!(missing.unwrap().0.tokens.is_empty());
+        assert!(Tokens::load(&Figment::from(Toml::string("[auth]\ntokens = 3\n"))).is_err());
+        assert!(Tokens::load(&Figment::new()).unwrap().0.tokens.is_empty());
+        let bad_user = "[[auth.tokens]]\ntoken = \"t\"\nuser = \"../root\"\nscopes = []\n";
+        assert!(Tokens::load(&Figment::from(Toml::string(bad_user))).is_err());
+    }
+
+    #[test]
+    fn test_sweep_uses_each_users_retention() {
+        let dir = crate::testing::temp_dir("sweep");
+        let storage = Storage::open(dir.path()).unwrap();
+        let config = "[auth]\nretention_secs = 60\n[auth.users.keeper]\nretention_secs = 86400\n";
+        let (tokens, _) = Tokens::load(&Figment::from(Toml::string(config))).unwrap();
+
+        let an_hour_ago = std::time::SystemTime::now() - Duration::from_secs(3600);
+        for user in ["keeper", "other"] {
+            let workspace = storage.workspace(user).unwrap();
+            let id = workspace.create(b"image").unwrap();
+            std::fs::File::options()
+                .write(true)
+                .open(workspace.path(&id).unwrap())
+                .unwrap()
+                .set_modified(an_hour_ago)
+                .unwrap();
+        }
+        assert_eq!(tokens.sweep(&storage).unwrap().deleted, 1);
+        assert_eq!(storage.workspace("keeper").unwrap().list().unwrap().len(), 1);
+        assert!(storage.workspace("other").unwrap().list().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::pixels::Pixels;
+use crate::Error;
+
+/// Renders bit `bit` of `channel` as a 1-bit grayscale image, white where
+/// the bit is set.
+pub fn bit_plane(pixels: &Pixels, channel: usize, bit: u8) -> Result<Pixels, Error> {
+    check_plane(pixels, channel, bit)?;
+    let samples = plane_bits(pixels, channel, bit).collect();
+    Pixels::new(pixels.width(), pixels.height(), 1, 0, samples)
+}
+
+/// Renders where bit `bit` of `channel` differs between two images of the
+/// same shape, white where the bits differ.
+pub fn xor_diff(left: &Pixels, right: &Pixels, channel: usize, bit: u8) -> Result<Pixels, Error> {
+    check_plane(left, channel, bit)?;
+    if left.width() != right.width()
+        || left.height() != right.height()
+        || left.channels() != right.channels()
+        || left.bit_depth() != right.bit_depth()
+    {
+        return Err(Error::InvalidArgument(
+            "images must have the same dimensions and format".to_string(),
+        ));
+    }
+    let samples = plane_bits(left, channel, bit)
+        .zip(plane_bits(right, channel, bit))
+        .map(|(a, b)| a ^ b)
+        .collect();
+    Pixels::new(left.width(), left.height(), 1, 0, samples)
+}
+
+fn check_plane(pixels: &Pixels, channel: usize, bit: u8) -> Result<(), Error> {
+    if channel >= pixels.channels() {
+        return Err(Error::InvalidArgument(format!(
+            "channel {} out of range, image has {} channels",
+            channel,
+            pixels.channels()
+        )));
+    }
+    if bit >= pixels.bit_depth() {
+        return Err(Error::InvalidArgument(format!(
+            "bit {} out of range for bit depth {}",
+            bit,
+            pixels.bit_depth()
+        )));
+    }
+    Ok(())
+}
+
+fn plane_bits(pixels: &Pixels, channel: usize, bit: u8) -> impl Iterator<Item = u16> + '_ {
+    pixels
+        .samples()
+        .iter()
+        .skip(channel)
+        .step_by(pixels.channels())
+        .map(move |&s| (s >> bit) & 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rgb() -> Pixels {
+        Pixels::new(2, 2, 8, 2, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12]).unwrap()
+    }
+
+    #[test]
+    fn test_bit_plane() {
+        let plane = bit_plane(&rgb(), 1, 0).unwrap();
+        assert_eq!(plane.bit_depth(), 1);
+        assert_eq!(plane.channels(), 1);
+        assert_eq!(plane.samples(), &[0, 1, 0, 1]);
+
+        let plane = bit_plane(&rgb(), 2, 3).unwrap();
+        assert_eq!(plane.samples(), &[0, 0, 1, 1]);
+    }
+
+    #[test]
+    fn test_xor_diff() {
+        let left = rgb();
+        let mut right = rgb();
+        right.samples_mut()[3] ^= 1;
+        let diff = xor_diff(&left, &right, 0, 0).unwrap();
+        assert_eq!(diff.samples(), &[0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn test_out_of_range() {
+        assert!(bit_plane(&rgb(), 3, 0).is_err());
+        assert!(bit_plane(&rgb(), 0, 8).is_err());
+    }
+
+    #[test]
+    fn test_mismatched_images() {
+        let other = Pixels::new(1, 1, 8, 2, vec![0, 0, 0]).unwrap();
+        assert!(xor_diff(&rgb(), &other, 0, 0).is_err());
+    }
+}
@@ -16,29 +16,37 @@ impl TryFrom<&[u8]> for Chunk {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let data_len = value.len();
-        let mut iter = value.iter().cloned();
-        let first4: [u8; 4] = iter
-            .by_ref()
-            .take(4)
-            .collect::<Vec<u8>>()
-            .as_slice()
-            .try_into()?;
-        let length = u32::from_be_bytes(first4);
-
-        let second4: Vec<u8> = iter.by_ref().take(4).collect();
-        let chunk_type =
-            ChunkType::try_from(TryInto::<[u8; 4]>::try_into(second4.as_slice()).unwrap())?;
-
-        let data_bytes: Vec<u8> = iter.by_ref().take(data_len - 12).collect();
-
-        let last_bytes: [u8; 4] = iter.take(4).collect::<Vec<u8>>().as_slice().try_into()?;
-        let crc = u32::from_be_bytes(last_bytes);
-
-        let correct_crc =
-            crc == CRC.checksum(Self::get_bytes_for_crc(&chunk_type, &data_bytes).as_slice());
-        if !correct_crc {
-            Err(Error::from("Invalid chunk"))
+        if value.len() < 12 {
+            return Err(Error::TruncatedChunk {
+                needed: 12,
+                available: value.len(),
+            });
+        }
+        let length = u32::from_be_bytes([value[0], value[1], value[2], value[3]]);
+        let needed = (length as usize).saturating_add(12);
+        if value.len() < needed {
+            return Err(Error::TruncatedChunk {
+                needed,
+                available: value.len(),
+            });
+        }
+
+        let chunk_type = ChunkType::try_from([value[4], value[5], value[6], value[7]])?;
+        let data_bytes = value[8..needed - 4].to_vec();
+        let crc = u32::from_be_bytes([
+            value[needed - 4],
+            value[needed - 3],
+            value[needed - 2],
+            value[needed - 1],
+        ]);
+
+        let expected = CRC.checksum(Self::get_bytes_for_crc(&chunk_type, &data_bytes).as_slice());
+        if crc != expected {
+            Err(Error::CrcMismatch {
+                chunk_type: chunk_type.to_string(),
+                expected,
+                actual: crc,
+            })
         } else {
             Ok(Chunk {
                 data: data_bytes,
@@ -52,7 +60,7 @@ impl TryFrom<&[u8]> for Chunk {
 
 impl Display for Chunk {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.data_as_string().unwrap())
+        write!(f, "{}", String::from_utf8_lossy(&self.data))
     }
 }
 
@@ -67,26 +75,36 @@ impl Chunk {
         }
     }
 
+    /// CRC over the chunk type and data, as stored at the end of a chunk.
+    pub fn checksum(chunk_type: &[u8; 4], data: &[u8]) -> u32 {
+        let mut digest = CRC.digest();
+        digest.update(chunk_type);
+        digest.update(data);
+        digest.finalize()
+    }
+
     fn get_bytes_for_crc(chunk_type: &ChunkType, data: &Vec<u8>) -> Vec<u8> {
         let mut crc_bytes = vec![];
         crc_bytes.extend(chunk_type.bytes());
         crc_bytes.extend(data);
         crc_bytes
     }
-    fn length(&self) -> u32 {
+    pub fn length(&self) -> u32 {
         self.length
     }
     pub fn chunk_type(&self) -> &ChunkType {
         &self.chunk_type
     }
-    fn data(&self) -> &[u8] {
-        &[]
+    pub fn data(&self) -> &[u8] {
+        &self.data
     }
-    fn crc(&self) -> u32 {
+    pub fn crc(&self) -> u32 {
         self.crc
     }
     pub fn data_as_string(&self) -> Result<String, Error> {
-        Ok(String::from_utf8(self.data.clone()).unwrap())
+        String::from_utf8(self.data.clone()).map_err(|_| {
+            Error::InvalidArgument(format!("{} chunk data is not valid UTF-8", self.chunk_type))
+        })
     }
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut result_bytes = vec![];
@@ -207,6 +225,29 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_truncated_chunk_from_bytes() {
+        let chunk = testing_chunk();
+        let bytes = chunk.as_bytes();
+        for end in 0..bytes.len() {
+            assert!(Chunk::try_from(&bytes[..end]).is_err());
+        }
+
+        let mut huge = bytes.clone();
+        huge[..4].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(matches!(
+            Chunk::try_from(huge.as_ref()),
+            Err(Error::TruncatedChunk { .. })
+        ));
+    }
+
+    #[test]
+    fn test_non_utf8_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), vec![0xff, 0xfe]);
+        assert!(chunk.data_as_string().is_err());
+        let _chunk_string = format!("{}", chunk);
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
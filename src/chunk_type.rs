@@ -2,7 +2,9 @@ use crate::Error;
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
 
-#[derive(Debug, Eq, PartialEq)]
+/// A four letter PNG chunk type. Each letter's case carries one property
+/// bit: critical, public, reserved and safe-to-copy, in that order.
+#[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ChunkType {
     data: [u8; 4],
 }
@@ -12,24 +14,28 @@ impl ChunkType {
         self.data
     }
 
-    fn is_critical(&self) -> bool {
+    /// Decoders must understand critical chunks to display the image.
+    pub fn is_critical(&self) -> bool {
         self.data[0].is_ascii_uppercase()
     }
 
-    fn is_public(&self) -> bool {
+    /// Public chunks are defined by the PNG specification or its registry.
+    pub fn is_public(&self) -> bool {
         self.data[1].is_ascii_uppercase()
     }
 
-    fn is_reserved_bit_valid(&self) -> bool {
+    /// The third letter must be uppercase in the current PNG specification.
+    pub fn is_reserved_bit_valid(&self) -> bool {
         self.data[2].is_ascii_uppercase()
     }
 
-    fn is_safe_to_copy(&self) -> bool {
+    /// Editors that do not recognise the chunk may copy it into a modified image.
+    pub fn is_safe_to_copy(&self) -> bool {
         self.data[3].is_ascii_lowercase()
     }
 
     pub fn is_valid(&self) -> bool {
-        self.is_reserved_bit_valid() && self.data.into_iter().all(|val| val.is_ascii())
+        self.is_reserved_bit_valid() && self.data.iter().all(u8::is_ascii_alphabetic)
     }
 }
 
@@ -37,6 +43,12 @@ impl TryFrom<[u8; 4]> for ChunkType {
     type Error = Error;
 
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
+        if !value.iter().all(u8::is_ascii_alphabetic) {
+            return Err(Error::InvalidChunkType(format!(
+                "chunk type must be four ASCII letters, got {:?}",
+                String::from_utf8_lossy(&value)
+            )));
+        }
         Ok(ChunkType { data: value })
     }
 }
@@ -45,23 +57,16 @@ impl FromStr for ChunkType {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        return if s.chars().any(|val| val.is_numeric()) {
-            Err(Error::from("numeric values not allowed in chunk type"))
-        } else {
-            Ok(ChunkType {
-                data: s.as_bytes().try_into().unwrap(),
-            })
-        };
+        let data: [u8; 4] = s.as_bytes().try_into().map_err(|_| {
+            Error::InvalidChunkType(format!("chunk type must be 4 letters, got {:?}", s))
+        })?;
+        ChunkType::try_from(data)
     }
 }
 
 impl Display for ChunkType {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}",
-            String::from_utf8(self.data.try_into().unwrap()).unwrap()
-        )
+        write!(f, "{}", String::from_utf8_lossy(&self.data))
     }
 }
 
@@ -149,6 +154,22 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    pub fn test_chunk_type_rejects_non_letters() {
+        assert!(ChunkType::from_str("Ru_t").is_err());
+        assert!(ChunkType::from_str("Ru t").is_err());
+        assert!(ChunkType::try_from([82, 117, 0, 116]).is_err());
+        assert!(ChunkType::try_from([82, 117, 0xd3, 116]).is_err());
+    }
+
+    #[test]
+    pub fn test_chunk_type_wrong_length() {
+        assert!(ChunkType::from_str("").is_err());
+        assert!(ChunkType::from_str("RuS").is_err());
+        assert!(ChunkType::from_str("RuStY").is_err());
+        assert!(ChunkType::from_str("Ruéé").is_err());
+    }
+
     #[test]
     pub fn test_chunk_type_string() {
         let chunk = ChunkType::from_str("RuSt").unwrap();
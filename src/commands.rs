@@ -1,112 +1,788 @@
+use crate::analysis::{self, AnalysisReport};
+use crate::audit::{self, AuditReport};
+use crate::bitplane;
 use crate::chunk::Chunk;
 use crate::chunk_type::ChunkType;
-use crate::png::Png;
+use crate::lsb::{self, PixelMethod};
+use crate::pixels::Pixels;
+use crate::png::{ChunkInfo, Occurrence, Png, Position};
+use crate::progress::{Observer, Progress};
+use crate::repair::{self, RepairReport};
+use crate::sanitize::{self, SanitizeReport};
+use crate::stream::{ChunkReader, ChunkWriter};
+use crate::Error;
 use std::fs;
 use std::fs::File;
-use std::io::Read;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
 use std::str::FromStr;
+use tempfile::NamedTempFile;
 
-pub fn print(path: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+/// Where [`encode`], [`remove`], [`embed`], [`write_overlay`] and [`sanitize`]
+/// write the edited image.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Output {
+    /// A separate file, which must not be the input itself.
+    File(String),
+    /// Over the input, first copying the original to `{path}.bak` if
+    /// `backup` is set.
+    InPlace { backup: bool },
+}
+
+impl Output {
+    /// A new file next to `input`, `image.png` becoming `image.out.png`.
+    pub fn beside(input: &str) -> Output {
+        let path = std::path::Path::new(input);
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let name = match path.extension() {
+            Some(extension) => format!("{}.out.{}", stem, extension.to_string_lossy()),
+            None => format!("{}.out", stem),
+        };
+        Output::File(path.with_file_name(name).to_string_lossy().into_owned())
+    }
+
+    /// The file written for `input`.
+    pub fn path<'a>(&'a self, input: &'a str) -> &'a str {
+        match self {
+            Output::File(path) => path,
+            Output::InPlace { .. } => input,
+        }
+    }
+}
+
+/// The chunk types of an image in file order, and how much data follows IEND.
+pub struct Listing {
+    pub chunk_types: Vec<String>,
+    pub overlay_bytes: u64,
+}
+
+pub fn print(path: &str) -> Result<Listing, Error> {
 
     if !file_exists(path)? {
-        return Err(Box::from(format!("File not found -: {}", path)));
+        return Err(Error::FileNotFound(path.to_string()));
     }
 
-    let buffer = get_bytes_from_path(path);
-    let png = Png::try_from(buffer.as_slice())?;
+    let mut reader = chunk_reader(path)?;
+    let chunk_types = reader
+        .by_ref()
+        .map(|c| c.map(|c| c.chunk_type().to_string()))
+        .collect::<Result<Vec<String>, Error>>()?;
+    let overlay_bytes = io::copy(&mut reader.into_inner(), &mut io::sink())?;
 
-    let chunk_types: Vec<String> = png
-        .chunks()
-        .iter()
-        .map(|c| c.chunk_type().to_string())
-        .collect();
-    
-    Ok(chunk_types) // Return the chunk types
+    Ok(Listing {
+        chunk_types,
+        overlay_bytes,
+    })
 }
 
 
 
-pub fn encode(path: &str, chunk_type: &str, message: &str) -> Result<bool, Box<dyn std::error::Error>> {
+pub fn audit(path: &str) -> Result<AuditReport, Error> {
 
     if !file_exists(path)? {
-        return Err(Box::from(format!("File not found -: {}", path)));
+        return Err(Error::FileNotFound(path.to_string()));
     }
 
-    let buffer = get_bytes_from_path(path);
-    let mut png = Png::try_from(buffer.as_slice()).unwrap();
+    let buffer = get_bytes_from_path(path)?;
+    Ok(audit::audit(&buffer))
+}
+
+/// Inserts a chunk holding `message` at `position`. Critical, public and
+/// reserved chunk types are refused unless `force` is set, since writing
+/// them produces an image other decoders may reject or misread.
+pub fn encode(
+    path: &str,
+    chunk_type: &str,
+    message: &str,
+    position: &Position,
+    force: bool,
+    output: &Output,
+) -> Result<bool, Error> {
+    encode_with_progress(path, chunk_type, message, position, force, output, &mut |_| Ok(()))
+}
 
-    let i_end = png
-        .remove_chunk("IEND")
-        .map_err(|_| {
-        Box::<dyn std::error::Error>::from("Unable to remove end chunk")
+/// Like [`encode`], reporting each chunk as it is copied.
+pub fn encode_with_progress(
+    path: &str,
+    chunk_type: &str,
+    message: &str,
+    position: &Position,
+    force: bool,
+    output: &Output,
+    progress: Observer,
+) -> Result<bool, Error> {
+
+    if !file_exists(path)? {
+        return Err(Error::FileNotFound(path.to_string()));
+    }
+
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    if !force {
+        check_writable(&chunk_type)?;
+    }
+
+    let mut new_chunk = Some(Chunk::new(chunk_type.clone(), message.as_bytes().into()));
+    let mut seen: Vec<String> = vec![];
+    let mut previous: Option<ChunkType> = None;
+    rewrite_chunks(path, output, None, progress, |chunk, writer| {
+        if chunk.chunk_type().bytes() == *b"IEND" {
+            // Only now is the whole chunk list known.
+            let mut chunk_types = seen.clone();
+            chunk_types.push("IEND".to_string());
+            position.check(&chunk_types)?;
+        }
+        if position.inserts_before(&chunk_type, seen.len(), chunk.chunk_type(), previous.as_ref()) {
+            if let Some(new_chunk) = new_chunk.take() {
+                writer.write_chunk(&new_chunk)?;
+            }
+        }
+        writer.write_chunk(&chunk)?;
+        seen.push(chunk.chunk_type().to_string());
+        previous = Some(chunk.chunk_type().clone());
+        Ok(())
     })?;
-    png.append_chunk(Chunk::new(
-        ChunkType::from_str(chunk_type).map_err(|_| {
-            Box::<dyn std::error::Error>::from("Invalid chunk type")
-        })?,
-        message.as_bytes().into(),
-    ));
-    png.append_chunk(i_end);
-
-    let write_path = std::path::Path::new(path);
-    fs::write(write_path, png.as_bytes())?;
-    println!("Message encoded!");
+    if new_chunk.is_some() {
+        return Err(Error::ChunkNotFound("IEND".to_string()));
+    }
     Ok(true)
 }
 
-pub fn decode(path: &str, chunk_type: &str) -> Result<String, Box<dyn std::error::Error>> {
+/// Every chunk of `chunk_type` with its index and data size.
+pub fn list(path: &str, chunk_type: &str) -> Result<Vec<ChunkInfo>, Error> {
+    let mut found = chunks(path)?;
+    found.retain(|chunk| chunk.chunk_type == chunk_type);
+    Ok(found)
+}
+
+/// Every chunk with its index and data size, in file order.
+pub fn chunks(path: &str) -> Result<Vec<ChunkInfo>, Error> {
+
+    if !file_exists(path)? {
+        return Err(Error::FileNotFound(path.to_string()));
+    }
+
+    let mut found = vec![];
+    for (index, chunk) in chunk_reader(path)?.enumerate() {
+        let chunk = chunk?;
+        found.push(ChunkInfo {
+            index,
+            chunk_type: chunk.chunk_type().to_string(),
+            size: chunk.length(),
+        });
+    }
+    Ok(found)
+}
+
+/// The messages in the selected occurrences of `chunk_type`, in file order.
+pub fn decode(path: &str, chunk_type: &str, occurrence: &Occurrence) -> Result<Vec<String>, Error> {
+    decode_with_progress(path, chunk_type, occurrence, &mut |_| Ok(()))
+}
+
+/// Like [`decode`], reporting each chunk as it is read.
+pub fn decode_with_progress(
+    path: &str,
+    chunk_type: &str,
+    occurrence: &Occurrence,
+    progress: Observer,
+) -> Result<Vec<String>, Error> {
 
     if !file_exists(path)? {
-        return Err(Box::from(format!("File not found -: {}", path)));
+        return Err(Error::FileNotFound(path.to_string()));
     }
 
-    let buffer = get_bytes_from_path(path);
-    let png = Png::try_from(buffer.as_slice()).unwrap();
+    let bytes_total = fs::metadata(path)?.len();
+    let mut reader = chunk_reader(path)?;
+    let mut messages = vec![];
+    let mut seen = 0;
+    let mut chunks_processed = 0;
+    while let Some(chunk) = reader.next_chunk()? {
+        chunks_processed += 1;
+        progress(Progress::Parsing {
+            bytes_parsed: reader.position(),
+            bytes_total,
+            chunks_processed,
+        })?;
+        if chunk.chunk_type().to_string() == chunk_type {
+            seen += 1;
+            if occurrence.selects(seen - 1) {
+                messages.push(
+                    chunk
+                        .data_as_string()
+                        .unwrap_or_else(|_| "Could not convert data to string".to_string()),
+                );
+                if *occurrence == Occurrence::First {
+                    break;
+                }
+            }
+        }
+    }
+    if messages.is_empty() {
+        return Err(Error::ChunkNotFound(chunk_type.to_string()));
+    }
 
-    let target = png.chunk_by_type(chunk_type).ok_or_else(|| {
-        Box::<dyn std::error::Error>::from(format!("No chunk found with type -: {}", chunk_type))
+    Ok(messages)
+}
+
+/// Like [`encode`], but on an image held in memory, returning the new image.
+pub fn encode_bytes(
+    image: &[u8],
+    chunk_type: &str,
+    data: &[u8],
+    position: &Position,
+    force: bool,
+) -> Result<Vec<u8>, Error> {
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    if !force {
+        check_writable(&chunk_type)?;
+    }
+
+    let mut png = Png::try_from(image)?;
+    png.insert_chunk(Chunk::new(chunk_type, data.to_vec()), position)?;
+    Ok(png.as_bytes())
+}
+
+/// Like [`decode`], but on an image held in memory, returning the raw data
+/// of the selected chunks.
+pub fn decode_bytes(
+    image: &[u8],
+    chunk_type: &str,
+    occurrence: &Occurrence,
+) -> Result<Vec<Vec<u8>>, Error> {
+    let png = Png::try_from(image)?;
+    let found: Vec<Vec<u8>> = png
+        .chunks_by_type(chunk_type)
+        .enumerate()
+        .filter(|(n, _)| occurrence.selects(*n))
+        .map(|(_, (_, chunk))| chunk.data().to_vec())
+        .collect();
+    if found.is_empty() {
+        return Err(Error::ChunkNotFound(chunk_type.to_string()));
+    }
+    Ok(found)
+}
+
+/// Removes the selected occurrences of `chunk_type`, returning how many
+/// chunks were removed.
+pub fn remove(
+    path: &str,
+    chunk_type: &str,
+    occurrence: &Occurrence,
+    output: &Output,
+) -> Result<usize, Error> {
+
+    if !file_exists(path)? {
+        return Err(Error::FileNotFound(path.to_string()));
+    }
+
+    let mut seen = 0;
+    let mut removed = 0;
+    rewrite_chunks(path, output, None, &mut |_| Ok(()), |chunk, writer| {
+        if chunk.chunk_type().to_string() == chunk_type {
+            seen += 1;
+            if occurrence.selects(seen - 1) {
+                removed += 1;
+                return Ok(());
+            }
+        }
+        writer.write_chunk(&chunk)
     })?;
+    if removed == 0 {
+        return Err(Error::ChunkNotFound(chunk_type.to_string()));
+    }
 
-    println!("Message is: {}", target.data_as_string().unwrap());
+    Ok(removed)
+}
 
-    Ok(target.data_as_string().unwrap_or_else(|_| "Could not convert data to string".to_string()))
+/// Hides `message` in the pixels under `key`. Images whose pixels would
+/// take up more than `limit` bytes are refused, see [`Pixels::decode`].
+pub fn embed(path: &str, message: &str, key: &str, method: PixelMethod, limit: usize, output: &Output) -> Result<bool, Error> {
+    embed_with_progress(path, message, key, method, limit, output, &mut |_| Ok(()))
 }
 
-pub fn remove(path: &str, chunk_type: &str) -> Result<bool, Box<dyn std::error::Error>> {
+/// Like [`embed`], reporting the parsed image and then the bits written.
+pub fn embed_with_progress(
+    path: &str,
+    message: &str,
+    key: &str,
+    method: PixelMethod,
+    limit: usize,
+    output: &Output,
+    progress: Observer,
+) -> Result<bool, Error> {
 
     if !file_exists(path)? {
-        return Err(Box::from(format!("File not found -: {}", path)));
+        return Err(Error::FileNotFound(path.to_string()));
     }
+    check_output(path, output)?;
 
-    let buffer = get_bytes_from_path(path);
-    let mut png = Png::try_from(buffer.as_slice()).unwrap();
+    let mut png = parse_file(path, progress)?;
 
-    png.remove_chunk(chunk_type).map_err(|_| {
-        Box::<dyn std::error::Error>::from(format!("Unable to remove chunk -: {}", chunk_type))
-    })?;
+    let mut pixels = Pixels::decode(&png, limit)?;
+    lsb::embed_with_progress(&mut pixels, message.as_bytes(), key, method, progress)?;
+    png.set_pixels(&pixels)?;
+
+    write_output(path, output, &png.as_bytes())?;
+    Ok(true)
+}
+
+/// Reads the message [`embed`] hid under `key`, refusing images over
+/// `limit` bytes decoded.
+pub fn extract(path: &str, key: &str, limit: usize) -> Result<String, Error> {
+    extract_with_progress(path, key, limit, &mut |_| Ok(()))
+}
+
+/// Like [`extract`], reporting the parsed image and then the bits read.
+pub fn extract_with_progress(path: &str, key: &str, limit: usize, progress: Observer) -> Result<String, Error> {
+
+    if !file_exists(path)? {
+        return Err(Error::FileNotFound(path.to_string()));
+    }
+
+    let png = parse_file(path, progress)?;
+
+    let payload = lsb::extract_with_progress(&Pixels::decode(&png, limit)?, key, progress)?;
+    String::from_utf8(payload).map_err(|_| {
+        Error::InvalidArgument("Extracted payload is not valid UTF-8".to_string())
+    })
+}
+
+/// Runs the steganalysis attacks on the pixels, refusing images over
+/// `limit` bytes decoded.
+pub fn analyze(path: &str, limit: usize) -> Result<AnalysisReport, Error> {
+    analyze_with_progress(path, limit, &mut |_| Ok(()))
+}
+
+/// Like [`analyze`], reporting the parsed image and then each channel.
+pub fn analyze_with_progress(path: &str, limit: usize, progress: Observer) -> Result<AnalysisReport, Error> {
+
+    if !file_exists(path)? {
+        return Err(Error::FileNotFound(path.to_string()));
+    }
+
+    let png = parse_file(path, progress)?;
+
+    analysis::analyze_with_progress(&Pixels::decode(&png, limit)?, progress)
+}
+
+/// Renders a bit plane (or the XOR of one against `diff`) as PNG bytes,
+/// refusing images over `limit` bytes decoded.
+pub fn render_bitplane(path: &str, channel: usize, bit: u8, diff: Option<&str>, limit: usize) -> Result<Vec<u8>, Error> {
+
+    if !file_exists(path)? {
+        return Err(Error::FileNotFound(path.to_string()));
+    }
+
+    let buffer = get_bytes_from_path(path)?;
+    let pixels = Pixels::decode(&Png::try_from(buffer.as_slice())?, limit)?;
+
+    let plane = match diff {
+        Some(other) => {
+            if !file_exists(other)? {
+                return Err(Error::FileNotFound(other.to_string()));
+            }
+            let other_buffer = get_bytes_from_path(other)?;
+            let other_pixels = Pixels::decode(&Png::try_from(other_buffer.as_slice())?, limit)?;
+            bitplane::xor_diff(&pixels, &other_pixels, channel, bit)?
+        }
+        None => bitplane::bit_plane(&pixels, channel, bit)?,
+    };
+
+    Ok(Png::from_pixels(&plane).as_bytes())
+}
 
-    let write_path = std::path::Path::new(path);
-    fs::write(write_path, png.as_bytes())?;
-    println!("Chunk removed!");
+pub fn bitplane(path: &str, channel: usize, bit: u8, diff: Option<&str>, limit: usize, output: &Output) -> Result<bool, Error> {
+    check_output(path, output)?;
+    if let Some(diff) = diff {
+        check_output(diff, output)?;
+    }
+    let bytes = render_bitplane(path, channel, bit, diff, limit)?;
 
+    write_output(path, output, &bytes)?;
     Ok(true)
 }
-fn get_bytes_from_path(path: &str) -> Vec<u8> {
-    let mut f = File::open(path).expect("no file found");
+
+pub fn sanitize(path: &str, output: &Output, allow: &[String], randomize_lsb: bool, limit: usize) -> Result<SanitizeReport, Error> {
+
+    if !file_exists(path)? {
+        return Err(Error::FileNotFound(path.to_string()));
+    }
+    check_output(path, output)?;
+
+    let buffer = get_bytes_from_path(path)?;
+    let (png, report) = sanitize::sanitize(&buffer, allow, randomize_lsb, limit)?;
+
+    write_output(path, output, &png.as_bytes())?;
+    Ok(report)
+}
+
+/// Recovers what it can from a damaged image, writing the result to `output`
+/// when one is given.
+pub fn repair(path: &str, output: Option<&Output>, fix_crc: bool) -> Result<RepairReport, Error> {
+
+    if !file_exists(path)? {
+        return Err(Error::FileNotFound(path.to_string()));
+    }
+    if let Some(output) = output {
+        check_output(path, output)?;
+    }
+
+    let buffer = get_bytes_from_path(path)?;
+    let (png, report) = repair::repair(&buffer, fix_crc)?;
+
+    if let Some(output) = output {
+        write_output(path, output, &png.as_bytes())?;
+    }
+    Ok(report)
+}
+
+/// The data appended after IEND, empty when there is none.
+pub fn read_overlay(path: &str) -> Result<Vec<u8>, Error> {
+
+    if !file_exists(path)? {
+        return Err(Error::FileNotFound(path.to_string()));
+    }
+
+    let mut reader = chunk_reader(path)?;
+    for chunk in reader.by_ref() {
+        chunk?;
+    }
+    let mut overlay = vec![];
+    reader.into_inner().read_to_end(&mut overlay)?;
+    Ok(overlay)
+}
+
+/// Replaces whatever follows IEND with `data`; empty `data` removes the
+/// overlay. Returns the size of the overlay that was replaced.
+pub fn write_overlay(path: &str, data: &[u8], output: &Output) -> Result<u64, Error> {
+
+    if !file_exists(path)? {
+        return Err(Error::FileNotFound(path.to_string()));
+    }
+
+    rewrite_chunks(
+        path,
+        output,
+        Some(data),
+        &mut |_| Ok(()),
+        |chunk, writer| writer.write_chunk(&chunk),
+    )
+}
+
+fn check_writable(chunk_type: &ChunkType) -> Result<(), Error> {
+    let reason = if chunk_type.is_critical() {
+        "is critical, so decoders that do not know it will refuse to display the image"
+    } else if chunk_type.is_public() {
+        "is public, a name reserved for chunks defined by the PNG specification"
+    } else if !chunk_type.is_reserved_bit_valid() {
+        "has a lowercase third letter, which the PNG specification reserves"
+    } else {
+        return Ok(());
+    };
+    Err(Error::InvalidChunkType(format!(
+        "{} {}; use a type like ruSt, or force the write",
+        chunk_type, reason
+    )))
+}
+
+fn chunk_reader(path: &str) -> Result<ChunkReader<BufReader<File>>, Error> {
+    ChunkReader::new(BufReader::new(File::open(path)?))
+}
+
+/// Streams the chunks of `path` through `edit` into a temporary file next
+/// to the output, then moves the result into place. Each call gets a
+/// temporary file of its own, so concurrent edits cannot mix. Bytes after IEND are
+/// copied unchanged, or swapped for `overlay` when one is given; either way
+/// the size of the original overlay is returned. Only one chunk is held in
+/// memory at a time. Nothing is written if reading or `edit` fails.
+fn rewrite_chunks<F>(
+    path: &str,
+    output: &Output,
+    overlay: Option<&[u8]>,
+    progress: Observer,
+    mut edit: F,
+) -> Result<u64, Error>
+where
+    F: FnMut(Chunk, &mut ChunkWriter<BufWriter<File>>) -> Result<(), Error>,
+{
+    check_output(path, output)?;
+    let temp = temp_output(path, output)?;
+    let result = (|| {
+        let bytes_total = fs::metadata(path)?.len();
+        let mut reader = chunk_reader(path)?;
+        let mut writer = ChunkWriter::new(BufWriter::new(temp.reopen()?))?;
+        let mut seen_iend = false;
+        let mut chunks_processed = 0;
+        while let Some(chunk) = reader.next_chunk()? {
+            seen_iend = chunk.chunk_type().bytes() == *b"IEND";
+            edit(chunk, &mut writer)?;
+            chunks_processed += 1;
+            progress(Progress::Parsing {
+                bytes_parsed: reader.position(),
+                bytes_total,
+                chunks_processed,
+            })?;
+        }
+        // Without IEND a new overlay would read back as a broken chunk.
+        if overlay.is_some() && !seen_iend {
+            return Err(Error::ChunkNotFound("IEND".to_string()));
+        }
+        let mut output = writer.finish()?;
+        let original = match overlay {
+            Some(overlay) => {
+                output.write_all(overlay)?;
+                io::copy(&mut reader.into_inner(), &mut io::sink())?
+            }
+            None => io::copy(&mut reader.into_inner(), &mut output)?,
+        };
+        output.flush()?;
+        Ok(original)
+    })();
+    commit_output(path, output, temp, result)
+}
+
+/// Writes `bytes` as the edited version of `path` the way
+/// [`rewrite_chunks`] does, through a temporary file moved into place.
+fn write_output(path: &str, output: &Output, bytes: &[u8]) -> Result<(), Error> {
+    check_output(path, output)?;
+    let temp = temp_output(path, output)?;
+    let result = temp.as_file().write_all(bytes).map_err(Error::from);
+    commit_output(path, output, temp, result)
+}
+
+/// Refuses an [`Output::File`] that is the input itself, which would
+/// destroy the original without the caller asking for an in-place edit.
+fn check_output(path: &str, output: &Output) -> Result<(), Error> {
+    if let Output::File(target) = output {
+        if same_file(path, target)? {
+            return Err(Error::InvalidArgument(format!(
+                "output {} is the input image; edit it in place explicitly instead",
+                target
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// A fresh temporary file in the directory of the output, so the result
+/// can be renamed into place. It is deleted if dropped before
+/// [`commit_output`].
+fn temp_output(path: &str, output: &Output) -> Result<NamedTempFile, Error> {
+    let dir = match Path::new(output.path(path)).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    Ok(tempfile::Builder::new().prefix(".pngme-").suffix(".tmp").tempfile_in(dir)?)
+}
+
+/// Moves the finished `temp` over the output of `path`, keeping a backup
+/// first if asked to. A failed write just drops, and so deletes, `temp`.
+fn commit_output<T>(path: &str, output: &Output, temp: NamedTempFile, result: Result<T, Error>) -> Result<T, Error> {
+    let value = result?;
+    // Temporary files are private to their owner; the result is not.
+    fs::set_permissions(temp.path(), fs::metadata(path)?.permissions())?;
+    if *output == (Output::InPlace { backup: true }) {
+        fs::copy(path, format!("{}.bak", path))?;
+    }
+    temp.persist(output.path(path)).map_err(|e| Error::Io(e.error))?;
+    Ok(value)
+}
+
+fn same_file(a: &str, b: &str) -> Result<bool, Error> {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => Ok(a == b),
+        (Err(e), _) => Err(Error::Io(e)),
+        (_, Err(_)) => Ok(false),
+    }
+}
+
+/// Reads and parses the whole of `path`, reporting it as one step.
+fn parse_file(path: &str, progress: Observer) -> Result<Png, Error> {
+    let buffer = get_bytes_from_path(path)?;
+    let png = Png::try_from(buffer.as_slice())?;
+    progress(Progress::Parsing {
+        bytes_parsed: buffer.len() as u64,
+        bytes_total: buffer.len() as u64,
+        chunks_processed: png.chunks().len(),
+    })?;
+    Ok(png)
+}
+
+fn get_bytes_from_path(path: &str) -> Result<Vec<u8>, Error> {
+    let mut f = File::open(path)?;
     let mut buffer = Vec::new();
-    f.read_to_end(&mut buffer).expect("buffer overflow");
-    buffer
+    f.read_to_end(&mut buffer)?;
+    Ok(buffer)
 }
 
-pub fn file_exists(path: &str) -> Result<bool, Box<dyn std::error::Error>> {
+pub fn file_exists(path: &str) -> Result<bool, Error> {
     match fs::metadata(path) {
         Ok(_) => Ok(true),
         Err(e) => {
             if e.kind() == std::io::ErrorKind::NotFound {
                 Ok(false)
             } else {
-                Err(Box::new(e))
+                Err(Error::Io(e))
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::{Pixels, DEFAULT_DECODE_LIMIT};
+    use crate::testing;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    fn carrier(name: &str) -> (TempDir, PathBuf) {
+        let dir = testing::temp_dir(name);
+        let path = dir.path().join("image.png");
+        fs::write(&path, testing::carrier()).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn test_output_beside() {
+        assert_eq!(
+            Output::beside("dir/image.png"),
+            Output::File("dir/image.out.png".to_string())
+        );
+        assert_eq!(Output::beside("image"), Output::File("image.out".to_string()));
+    }
+
+    #[test]
+    fn test_encode_keeps_original() {
+        let (_dir, path) = carrier("keep");
+        let input = path.to_str().unwrap();
+        let original = fs::read(&path).unwrap();
+        let output = Output::beside(input);
+
+        encode(input, "ruSt", "hi", &Position::End, false, &output).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), original);
+        assert_eq!(decode(output.path(input), "ruSt", &Occurrence::First).unwrap(), ["hi"]);
+
+        remove(output.path(input), "ruSt", &Occurrence::First, &Output::InPlace { backup: true }).unwrap();
+        assert!(decode(output.path(input), "ruSt", &Occurrence::First).is_err());
+        let backup = format!("{}.bak", output.path(input));
+        assert_eq!(decode(&backup, "ruSt", &Occurrence::First).unwrap(), ["hi"]);
+    }
+
+    #[test]
+    fn test_encode_auto_places_text_before_idat() {
+        let (_dir, path) = carrier("auto");
+        let pixels = Pixels::new(2, 2, 8, 0, vec![0; 4]).unwrap();
+        fs::write(&path, Png::from_pixels(&pixels).as_bytes()).unwrap();
+        let input = path.to_str().unwrap();
+        let in_place = Output::InPlace { backup: false };
+
+        encode(input, "tEXt", "note", &Position::Auto, true, &in_place).unwrap();
+        encode(input, "ruSt", "hi", &Position::Auto, false, &in_place).unwrap();
+        let types: Vec<String> = chunks(input).unwrap().into_iter().map(|c| c.chunk_type).collect();
+        let text = types.iter().position(|t| t == "tEXt").unwrap();
+        let idat = types.iter().position(|t| t == "IDAT").unwrap();
+        assert!(text < idat);
+        assert_eq!(types[types.len() - 2..], ["ruSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_overlay_keeps_original() {
+        let (_dir, path) = carrier("overlay");
+        let input = path.to_str().unwrap();
+        let original = fs::read(&path).unwrap();
+        let output = Output::beside(input);
+
+        write_overlay(input, b"tail", &output).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), original);
+        assert_eq!(read_overlay(output.path(input)).unwrap(), b"tail");
+
+        assert_eq!(write_overlay(output.path(input), &[], &Output::InPlace { backup: false }).unwrap(), 4);
+        assert!(read_overlay(output.path(input)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_repair_refuses_its_input() {
+        let (_dir, path) = carrier("repair");
+        let input = path.to_str().unwrap();
+        let original = fs::read(&path).unwrap();
+
+        let output = Output::File(input.to_string());
+        assert!(matches!(repair(input, Some(&output), false), Err(Error::InvalidArgument(_))));
+        assert!(matches!(bitplane(input, 0, 0, None, DEFAULT_DECODE_LIMIT, &output), Err(Error::InvalidArgument(_))));
+        assert_eq!(fs::read(&path).unwrap(), original);
+    }
+
+    #[test]
+    fn test_progress_covers_the_file() {
+        let (_dir, path) = carrier("progress");
+        let input = path.to_str().unwrap();
+        let mut reports = vec![];
+        encode_with_progress(input, "ruSt", "hi", &Position::End, false, &Output::InPlace { backup: false }, &mut |p| {
+            reports.push(p);
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(reports.len(), 1);
+
+        reports.clear();
+        decode_with_progress(input, "ruSt", &Occurrence::All, &mut |p| {
+            reports.push(p);
+            Ok(())
+        }).unwrap();
+        let size = fs::metadata(&path).unwrap().len();
+        assert_eq!(
+            reports,
+            [
+                Progress::Parsing { bytes_parsed: 8 + 14, bytes_total: size, chunks_processed: 1 },
+                Progress::Parsing { bytes_parsed: size, bytes_total: size, chunks_processed: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_observer_error_stops_the_command() {
+        let (_dir, path) = carrier("stopped");
+        let input = path.to_str().unwrap();
+        let mut reports = 0;
+        let result = decode_with_progress(input, "IEND", &Occurrence::All, &mut |_| {
+            reports += 1;
+            Err(Error::Cancelled)
+        });
+        assert!(matches!(result, Err(Error::Cancelled)));
+        assert_eq!(reports, 1);
+    }
+
+    #[test]
+    fn test_concurrent_edits_do_not_mix() {
+        let (_dir, path) = carrier("concurrent");
+        let input = path.to_str().unwrap();
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    let message = "x".repeat(64 * 1024);
+                    encode(input, "ruSt", &message, &Position::End, false, &Output::InPlace { backup: false }).unwrap();
+                });
+            }
+        });
+        // Edits may or may not see each other's chunk, but the image is whole.
+        let chunks = decode(input, "ruSt", &Occurrence::All).unwrap();
+        assert!((1..=8).contains(&chunks.len()));
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_output_must_differ_from_input() {
+        let (_dir, path) = carrier("same");
+        let input = path.to_str().unwrap();
+        let alias = path.parent().unwrap().join(".").join("image.png");
+        let output = Output::File(alias.to_string_lossy().into_owned());
+        assert!(matches!(
+            encode(input, "ruSt", "hi", &Position::End, false, &output),
+            Err(Error::InvalidArgument(_))
+        ));
+        let original = fs::read(&path).unwrap();
+        assert!(matches!(sanitize(input, &output, &[], false, DEFAULT_DECODE_LIMIT), Err(Error::InvalidArgument(_))));
+        assert_eq!(fs::read(&path).unwrap(), original);
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+}
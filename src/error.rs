@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter};
+
+/// Everything that can go wrong while reading, editing or writing a PNG.
+#[derive(Debug)]
+pub enum Error {
+    /// The data does not start with the 8 byte PNG signature.
+    InvalidSignature,
+    /// A chunk's stored CRC does not match its type and data.
+    CrcMismatch {
+        chunk_type: String,
+        expected: u32,
+        actual: u32,
+    },
+    /// The data ends before the chunk it describes.
+    TruncatedChunk { needed: usize, available: usize },
+    InvalidChunkType(String),
+    ChunkNotFound(String),
+    FileNotFound(String),
+    /// The chunks parse but do not describe a decodable image.
+    InvalidImage(String),
+    /// The image is valid PNG but uses a feature this crate cannot handle.
+    Unsupported(String),
+    /// A caller supplied argument is out of range or malformed.
+    InvalidArgument(String),
+    PayloadTooLarge { size: usize, capacity: usize },
+    /// Storing a file would take a workspace past its quota.
+    QuotaExceeded { needed: u64, quota: u64 },
+    AuthFailure(String),
+    /// The operation was cancelled before it finished.
+    Cancelled,
+    Io(std::io::Error),
+}
+
+impl Error {
+    /// Stable, machine-readable identifier for this kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Error::InvalidSignature => "invalid_signature",
+            Error::CrcMismatch { .. } => "crc_mismatch",
+            Error::TruncatedChunk { .. } => "truncated_chunk",
+            Error::InvalidChunkType(_) => "invalid_chunk_type",
+            Error::ChunkNotFound(_) => "chunk_not_found",
+            Error::FileNotFound(_) => "file_not_found",
+            Error::InvalidImage(_) => "invalid_image",
+            Error::Unsupported(_) => "unsupported",
+            Error::InvalidArgument(_) => "invalid_argument",
+            Error::PayloadTooLarge { .. } => "payload_too_large",
+            Error::QuotaExceeded { .. } => "quota_exceeded",
+            Error::AuthFailure(_) => "auth_failure",
+            Error::Cancelled => "cancelled",
+            Error::Io(_) => "io",
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::InvalidSignature => write!(f, "Invalid header"),
+            Error::CrcMismatch {
+                chunk_type,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "CRC mismatch in chunk {} -: expected {:#010x}, found {:#010x}",
+                chunk_type, expected, actual
+            ),
+            Error::TruncatedChunk { needed, available } => write!(
+                f,
+                "Truncated chunk -: needed {} bytes, {} available",
+                needed, available
+            ),
+            Error::InvalidChunkType(reason) => write!(f, "Invalid chunk type -: {}", reason),
+            Error::ChunkNotFound(chunk_type) => {
+                write!(f, "No chunk found with type -: {}", chunk_type)
+            }
+            Error::FileNotFound(path) => write!(f, "File not found -: {}", path),
+            Error::InvalidImage(reason) => write!(f, "Invalid image -: {}", reason),
+            Error::Unsupported(reason) => write!(f, "Unsupported image -: {}", reason),
+            Error::InvalidArgument(reason) => write!(f, "{}", reason),
+            Error::PayloadTooLarge { size, capacity } => write!(
+                f,
+                "payload of {} bytes exceeds capacity of {} bytes",
+                size, capacity
+            ),
+            Error::QuotaExceeded { needed, quota } => write!(
+                f,
+                "Quota exceeded -: {} bytes needed, quota is {} bytes",
+                needed, quota
+            ),
+            Error::AuthFailure(reason) => write!(f, "Authentication failed -: {}", reason),
+            Error::Cancelled => write!(f, "Operation cancelled"),
+            Error::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codes_are_stable() {
+        assert_eq!(Error::InvalidSignature.code(), "invalid_signature");
+        assert_eq!(Error::Cancelled.code(), "cancelled");
+        let crc = Error::CrcMismatch {
+            chunk_type: "RuSt".to_string(),
+            expected: 1,
+            actual: 2,
+        };
+        assert_eq!(crc.code(), "crc_mismatch");
+        assert_eq!(
+            crc.to_string(),
+            "CRC mismatch in chunk RuSt -: expected 0x00000001, found 0x00000002"
+        );
+    }
+
+    #[test]
+    fn test_io_source() {
+        use std::error::Error as _;
+        let e = Error::from(std::io::Error::other("disk"));
+        assert_eq!(e.code(), "io");
+        assert!(e.source().is_some());
+    }
+}
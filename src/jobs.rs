@@ -0,0 +1,384 @@
+use pngme::progress::Progress;
+use pngme::Error;
+use rocket::fairing::AdHoc;
+use rocket::serde::{Deserialize, Serialize};
+use schemars::JsonSchema;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{watch, Semaphore};
+
+/// The `jobs` table of Rocket.toml.
+#[derive(Debug, Deserialize)]
+pub struct JobsConfig {
+    /// Jobs run at the same time
+    #[serde(default = "default_workers")]
+    pub workers: usize,
+    /// Unfinished jobs accepted before new ones are refused
+    #[serde(default = "default_capacity")]
+    pub capacity: usize,
+    /// Seconds a finished job's result is kept for polling
+    #[serde(default = "default_retention")]
+    pub retention_secs: u64,
+}
+
+impl Default for JobsConfig {
+    fn default() -> JobsConfig {
+        JobsConfig {
+            workers: default_workers(),
+            capacity: default_capacity(),
+            retention_secs: default_retention(),
+        }
+    }
+}
+
+fn default_workers() -> usize {
+    2
+}
+
+fn default_capacity() -> usize {
+    64
+}
+
+fn default_retention() -> u64 {
+    3600
+}
+
+/// Where a job is in its life, with its result once it has one.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+#[serde(tag = "state", rename_all = "lowercase")]
+pub enum JobState {
+    Queued,
+    Running,
+    Succeeded { result: Value },
+    Failed { code: &'static str, message: String },
+    Cancelled,
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobState::Queued | JobState::Running)
+    }
+}
+
+/// A job as reported to its owner.
+#[derive(Clone, Debug, Serialize, JsonSchema)]
+pub struct JobView {
+    pub id: String,
+    pub kind: &'static str,
+    #[serde(flatten)]
+    pub state: JobState,
+    /// The last progress reported while running
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub progress: Option<Progress>,
+}
+
+struct Job {
+    // Workspace root of the caller that submitted the job
+    owner: PathBuf,
+    kind: &'static str,
+    state: JobState,
+    progress: Option<Progress>,
+    cancelled: Arc<AtomicBool>,
+    finished: Option<Instant>,
+    // Latest view, for callers streaming the job's events
+    updates: watch::Sender<JobView>,
+}
+
+/// What a job's work is handed to report progress and notice cancellation.
+pub struct Task {
+    id: String,
+    jobs: Arc<Jobs>,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl Task {
+    /// Whether the job was cancelled. Work should check before committing
+    /// anything, and undo what it did if the job was cancelled meanwhile.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn report(&self, progress: Progress) {
+        let mut jobs = self.jobs.lock();
+        if let Some(job) = jobs.get_mut(&self.id) {
+            job.progress = Some(progress);
+            job.updates.send_replace(view(&self.id, job));
+        }
+    }
+}
+
+/// Runs heavy commands off the request workers: at most `workers` at a
+/// time, with up to `capacity` waiting or running.
+pub struct Jobs {
+    jobs: Mutex<HashMap<String, Job>>,
+    workers: Arc<Semaphore>,
+    capacity: usize,
+    retention: Duration,
+}
+
+impl Jobs {
+    pub fn new(config: &JobsConfig) -> Jobs {
+        Jobs {
+            jobs: Mutex::new(HashMap::new()),
+            workers: Arc::new(Semaphore::new(config.workers.max(1))),
+            capacity: config.capacity,
+            retention: Duration::from_secs(config.retention_secs),
+        }
+    }
+
+    /// Queues `work` for the caller owning the workspace at `owner` and
+    /// returns the job ID, or `None` if the queue is full.
+    pub fn submit<F>(self: &Arc<Self>, owner: &Path, kind: &'static str, work: F) -> Option<String>
+    where
+        F: FnOnce(&Task) -> Result<Value, Error> + Send + 'static,
+    {
+        let id = format!("{:032x}", rand::random::<u128>());
+        let cancelled = Arc::new(AtomicBool::new(false));
+        {
+            let mut jobs = self.lock();
+            self.purge(&mut jobs);
+            if jobs.values().filter(|job| !job.state.is_finished()).count() >= self.capacity {
+                return None;
+            }
+            let state = JobState::Queued;
+            let (updates, _) = watch::channel(JobView {
+                id: id.clone(),
+                kind,
+                state: state.clone(),
+                progress: None,
+            });
+            jobs.insert(
+                id.clone(),
+                Job {
+                    owner: owner.to_path_buf(),
+                    kind,
+                    state,
+                    progress: None,
+                    cancelled: cancelled.clone(),
+                    finished: None,
+                    updates,
+                },
+            );
+        }
+
+        let jobs = self.clone();
+        let job_id = id.clone();
+        tokio::spawn(async move {
+            let Ok(_permit) = jobs.workers.clone().acquire_owned().await else {
+                return;
+            };
+            if cancelled.load(Ordering::SeqCst) {
+                return;
+            }
+            jobs.update(&job_id, JobState::Running);
+            let task = Task {
+                id: job_id.clone(),
+                jobs: jobs.clone(),
+                cancelled: cancelled.clone(),
+            };
+            let state = match tokio::task::spawn_blocking(move || work(&task)).await {
+                // Work that succeeded has committed its result, cancelled or not.
+                Ok(Ok(result)) => JobState::Succeeded { result },
+                _ if cancelled.load(Ordering::SeqCst) => JobState::Cancelled,
+                Ok(Err(e)) => JobState::Failed {
+                    code: e.code(),
+                    message: e.to_string(),
+                },
+                Err(e) => JobState::Failed {
+                    code: "internal",
+                    message: e.to_string(),
+                },
+            };
+            jobs.update(&job_id, state);
+        });
+        Some(id)
+    }
+
+    /// The job `id`, if it exists and belongs to `owner`.
+    pub fn get(&self, owner: &Path, id: &str) -> Option<JobView> {
+        let mut jobs = self.lock();
+        self.purge(&mut jobs);
+        jobs.get(id)
+            .filter(|job| job.owner == owner)
+            .map(|job| view(id, job))
+    }
+
+    /// Follows the job `id` as it changes, if it exists and belongs to
+    /// `owner`. The channel closes once the finished job is purged.
+    pub fn subscribe(&self, owner: &Path, id: &str) -> Option<watch::Receiver<JobView>> {
+        let jobs = self.lock();
+        jobs.get(id)
+            .filter(|job| job.owner == owner)
+            .map(|job| job.updates.subscribe())
+    }
+
+    /// Cancels the job `id`. A queued job never starts; a running one is
+    /// flagged, and ends as cancelled if its work notices and fails, undoing
+    /// any edit it made. Finished jobs are left as they are.
+    pub fn cancel(&self, owner: &Path, id: &str) -> Option<JobView> {
+        let mut jobs = self.lock();
+        let job = jobs.get_mut(id).filter(|job| job.owner == owner)?;
+        if !job.state.is_finished() {
+            job.cancelled.store(true, Ordering::SeqCst);
+            if matches!(job.state, JobState::Queued) {
+                job.state = JobState::Cancelled;
+                job.finished = Some(Instant::now());
+                job.updates.send_replace(view(id, job));
+            }
+        }
+        Some(view(id, job))
+    }
+
+    fn update(&self, id: &str, state: JobState) {
+        if let Some(job) = self.lock().get_mut(id) {
+            if job.state.is_finished() {
+                return;
+            }
+            if state.is_finished() {
+                job.finished = Some(Instant::now());
+            }
+            job.state = state;
+            job.updates.send_replace(view(id, job));
+        }
+    }
+
+    fn purge(&self, jobs: &mut HashMap<String, Job>) {
+        jobs.retain(|_, job| job.finished.is_none_or(|at| at.elapsed() < self.retention));
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Job>> {
+        self.jobs.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn view(id: &str, job: &Job) -> JobView {
+    JobView {
+        id: id.to_string(),
+        kind: job.kind,
+        state: job.state.clone(),
+        progress: job.progress.clone(),
+    }
+}
+
+/// Manages [`Jobs`] configured by the `jobs` table.
+pub fn fairing() -> AdHoc {
+    AdHoc::try_on_ignite("Jobs", |rocket| async move {
+        let config = match rocket.figment().find_value("jobs") {
+            Ok(_) => rocket.figment().extract_inner("jobs"),
+            Err(_) => Ok(JobsConfig::default()),
+        };
+        match config {
+            Ok(config) => Ok(rocket.manage(Arc::new(Jobs::new(&config)))),
+            Err(e) => {
+                eprintln!("Invalid jobs configuration: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn jobs(capacity: usize) -> Arc<Jobs> {
+        Arc::new(Jobs::new(&JobsConfig {
+            workers: 1,
+            capacity,
+            retention_secs: 60,
+        }))
+    }
+
+    async fn wait(jobs: &Jobs, owner: &Path, id: &str) -> JobState {
+        loop {
+            let state = jobs.get(owner, id).unwrap().state;
+            if state.is_finished() {
+                return state;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_jobs_run_and_report() {
+        let jobs = jobs(4);
+        let owner = Path::new("/alice");
+        let ok = jobs.submit(owner, "analyze", |_| Ok(Value::from(42))).unwrap();
+        let failed = jobs
+            .submit(owner, "extract", |_| Err(Error::InvalidArgument("bad key".to_string())))
+            .unwrap();
+
+        assert!(matches!(wait(&jobs, owner, &ok).await, JobState::Succeeded { result } if result == 42));
+        assert!(matches!(
+            wait(&jobs, owner, &failed).await,
+            JobState::Failed { code: "invalid_argument", .. }
+        ));
+        assert!(jobs.get(Path::new("/bob"), &ok).is_none());
+        assert!(jobs.cancel(Path::new("/bob"), &ok).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_cancel_and_capacity() {
+        let jobs = jobs(2);
+        let owner = Path::new("/alice");
+        let (release, blocked) = mpsc::channel::<()>();
+        let running = jobs
+            .submit(owner, "embed", move |task| {
+                blocked.recv().unwrap();
+                assert!(task.is_cancelled());
+                Err(Error::Cancelled)
+            })
+            .unwrap();
+        let queued = jobs
+            .submit(owner, "embed", |_| panic!("cancelled jobs never start"))
+            .unwrap();
+        assert!(jobs.submit(owner, "embed", |_| Ok(Value::Null)).is_none());
+
+        while !matches!(jobs.get(owner, &running).unwrap().state, JobState::Running) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        assert!(matches!(jobs.cancel(owner, &queued).unwrap().state, JobState::Cancelled));
+        jobs.cancel(owner, &running).unwrap();
+        release.send(()).unwrap();
+        assert!(matches!(wait(&jobs, owner, &running).await, JobState::Cancelled));
+        assert!(jobs.submit(owner, "embed", |_| Ok(Value::Null)).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_progress_is_streamed() {
+        let jobs = jobs(4);
+        let owner = Path::new("/alice");
+        let (release, blocked) = mpsc::channel::<()>();
+        let id = jobs
+            .submit(owner, "analyze", move |task| {
+                task.report(Progress::Analyzing {
+                    channels_analyzed: 1,
+                    channels_total: 3,
+                });
+                blocked.recv().unwrap();
+                Ok(Value::Null)
+            })
+            .unwrap();
+        let mut updates = jobs.subscribe(owner, &id).unwrap();
+        assert!(jobs.subscribe(Path::new("/bob"), &id).is_none());
+
+        let progress = Some(Progress::Analyzing {
+            channels_analyzed: 1,
+            channels_total: 3,
+        });
+        while updates.borrow_and_update().progress != progress {
+            updates.changed().await.unwrap();
+        }
+        assert_eq!(jobs.get(owner, &id).unwrap().progress, progress);
+        release.send(()).unwrap();
+        while !updates.borrow_and_update().state.is_finished() {
+            updates.changed().await.unwrap();
+        }
+        assert!(matches!(updates.borrow().state, JobState::Succeeded { .. }));
+    }
+}
@@ -0,0 +1,34 @@
+//! PNG chunk manipulation and steganography.
+//!
+//! The command line tool and the HTTP server are thin frontends over this
+//! library; build with `default-features = false` to use it without them.
+
+pub mod analysis;
+pub mod audit;
+pub mod bitplane;
+pub mod chunk;
+pub mod chunk_type;
+pub mod commands;
+pub mod error;
+pub mod lsb;
+pub mod pixels;
+pub mod png;
+pub mod progress;
+pub mod repair;
+pub mod sanitize;
+pub mod storage;
+pub mod stream;
+#[cfg(test)]
+mod testing;
+
+// Lets `testing`, which the binary's tests compile too, name this crate.
+#[cfg(test)]
+extern crate self as pngme;
+
+pub use chunk::Chunk;
+pub use chunk_type::ChunkType;
+pub use error::Error;
+pub use png::Png;
+
+// Result type alias
+pub type Result<T> = std::result::Result<T, Error>;
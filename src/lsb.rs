@@ -0,0 +1,318 @@
+use crate::pixels::Pixels;
+use crate::progress::{Observer, Progress, BITS_PER_REPORT};
+use crate::Error;
+use crc::{Crc, CRC_64_ECMA_182};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use serde::Deserialize;
+
+const KEY_CRC: Crc<u64> = Crc::<u64>::new(&CRC_64_ECMA_182);
+
+/// Number of carrier samples used by the big-endian payload length prefix.
+const LENGTH_BITS: usize = 32;
+
+/// How a sample is changed when its least significant bit has to flip.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[serde(rename_all = "lowercase")]
+pub enum PixelMethod {
+    /// Overwrite the LSB. Simple, but leaves pairs-of-values artefacts.
+    Replacement,
+    /// Randomly add or subtract one (±1 embedding), clamped at the sample bounds.
+    #[default]
+    Matching,
+}
+
+/// Seeds the stego key PRNG that drives both the carrier order and the
+/// ±1 decisions of LSB matching.
+fn key_rng(key: &str) -> ChaCha20Rng {
+    let mut seed = [0u8; 32];
+    for (i, word) in seed.chunks_mut(8).enumerate() {
+        let mut digest = KEY_CRC.digest();
+        digest.update(&[i as u8]);
+        digest.update(key.as_bytes());
+        word.copy_from_slice(&digest.finalize().to_be_bytes());
+    }
+    ChaCha20Rng::from_seed(seed)
+}
+
+fn carrier_indices(pixels: &Pixels) -> Result<Vec<usize>, Error> {
+    if pixels.is_palette() {
+        return Err(Error::Unsupported(
+            "palette images cannot carry pixel payloads".to_string(),
+        ));
+    }
+    Ok((0..pixels.samples().len())
+        .filter(|&i| !pixels.is_alpha_sample(i))
+        .collect())
+}
+
+fn carrier_order(pixels: &Pixels, rng: &mut ChaCha20Rng) -> Result<Vec<usize>, Error> {
+    let mut indices = carrier_indices(pixels)?;
+    indices.shuffle(rng);
+    Ok(indices)
+}
+
+/// Number of payload bytes `pixels` can hold.
+pub fn capacity(pixels: &Pixels) -> Result<usize, Error> {
+    Ok(carrier_indices(pixels)?.len().saturating_sub(LENGTH_BITS) / 8)
+}
+
+pub fn embed(
+    pixels: &mut Pixels,
+    payload: &[u8],
+    key: &str,
+    method: PixelMethod,
+) -> Result<(), Error> {
+    embed_with_progress(pixels, payload, key, method, &mut |_| Ok(()))
+}
+
+/// Like [`embed`], reporting the bits written as it goes.
+pub fn embed_with_progress(
+    pixels: &mut Pixels,
+    payload: &[u8],
+    key: &str,
+    method: PixelMethod,
+    progress: Observer,
+) -> Result<(), Error> {
+    let capacity = capacity(pixels)?;
+    if payload.len() > capacity {
+        return Err(Error::PayloadTooLarge {
+            size: payload.len(),
+            capacity,
+        });
+    }
+
+    let mut rng = key_rng(key);
+    let order = carrier_order(pixels, &mut rng)?;
+    let max = pixels.max_sample();
+    let samples = pixels.samples_mut();
+
+    let length = (payload.len() as u32).to_be_bytes();
+    let bits = length
+        .iter()
+        .chain(payload)
+        .flat_map(|byte| (0..8).rev().map(move |i| (byte >> i) as u16 & 1));
+
+    let bits_total = (length.len() + payload.len()) as u64 * 8;
+    for (n, (bit, index)) in bits.zip(order).enumerate() {
+        if (n as u64).is_multiple_of(BITS_PER_REPORT) {
+            progress(Progress::Embedding {
+                bits_embedded: n as u64,
+                bits_total,
+            })?;
+        }
+        let sample = samples[index];
+        if sample & 1 == bit {
+            continue;
+        }
+        samples[index] = match method {
+            PixelMethod::Replacement => sample ^ 1,
+            PixelMethod::Matching if sample == 0 => 1,
+            PixelMethod::Matching if sample == max => sample - 1,
+            PixelMethod::Matching if rng.gen::<bool>() => sample + 1,
+            PixelMethod::Matching => sample - 1,
+        };
+    }
+    progress(Progress::Embedding {
+        bits_embedded: bits_total,
+        bits_total,
+    })?;
+    Ok(())
+}
+
+/// Reads a payload written by [`embed`] with the same key. Both methods
+/// leave the message in the sample LSBs, so no method is needed here.
+pub fn extract(pixels: &Pixels, key: &str) -> Result<Vec<u8>, Error> {
+    extract_with_progress(pixels, key, &mut |_| Ok(()))
+}
+
+/// Like [`extract`], reporting the bits read once the payload length is
+/// known.
+pub fn extract_with_progress(pixels: &Pixels, key: &str, progress: Observer) -> Result<Vec<u8>, Error> {
+    let mut rng = key_rng(key);
+    let order = carrier_order(pixels, &mut rng)?;
+    let samples = pixels.samples();
+    let mut bits = order.iter().map(|&index| samples[index] as u8 & 1);
+    let mut next_byte = || {
+        bits.by_ref()
+            .take(8)
+            .fold(0u8, |byte, bit| (byte << 1) | bit)
+    };
+
+    let length = u32::from_be_bytes([next_byte(), next_byte(), next_byte(), next_byte()]);
+    if length as usize > capacity(pixels)? {
+        return Err(Error::InvalidArgument(
+            "No pixel payload found for this key".to_string(),
+        ));
+    }
+    let bits_total = (length as u64 + 4) * 8;
+    let mut payload = Vec::with_capacity(length as usize);
+    for n in 0..length as u64 {
+        if (n * 8).is_multiple_of(BITS_PER_REPORT) {
+            progress(Progress::Extracting {
+                bits_extracted: (n + 4) * 8,
+                bits_total,
+            })?;
+        }
+        payload.push(next_byte());
+    }
+    progress(Progress::Extracting {
+        bits_extracted: bits_total,
+        bits_total,
+    })?;
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cover(bit_depth: u8, color_type: u8, channels: usize, fill: u16) -> Pixels {
+        let samples = vec![fill; 32 * 32 * channels];
+        Pixels::new(32, 32, bit_depth, color_type, samples).unwrap()
+    }
+
+    fn noisy_cover() -> Pixels {
+        let samples = (0..32 * 32 * 3).map(|i| (i * 37 % 256) as u16).collect();
+        Pixels::new(32, 32, 8, 2, samples).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_matching() {
+        let mut pixels = noisy_cover();
+        embed(&mut pixels, b"hidden message", "key", PixelMethod::Matching).unwrap();
+        assert_eq!(extract(&pixels, "key").unwrap(), b"hidden message");
+    }
+
+    #[test]
+    fn test_round_trip_replacement() {
+        let mut pixels = noisy_cover();
+        embed(
+            &mut pixels,
+            b"hidden message",
+            "key",
+            PixelMethod::Replacement,
+        )
+        .unwrap();
+        assert_eq!(extract(&pixels, "key").unwrap(), b"hidden message");
+    }
+
+    #[test]
+    fn test_progress_reaches_total() {
+        let mut pixels = noisy_cover();
+        let payload = vec![7u8; 300];
+        let mut reports = vec![];
+        embed_with_progress(&mut pixels, &payload, "key", PixelMethod::Matching, &mut |p| {
+            reports.push(p);
+            Ok(())
+        }).unwrap();
+        assert_eq!(reports.len(), 2);
+        assert_eq!(
+            reports.last(),
+            Some(&Progress::Embedding {
+                bits_embedded: 304 * 8,
+                bits_total: 304 * 8,
+            })
+        );
+
+        let mut last = None;
+        extract_with_progress(&pixels, "key", &mut |p| {
+            last = Some(p);
+            Ok(())
+        }).unwrap();
+        assert_eq!(
+            last,
+            Some(Progress::Extracting {
+                bits_extracted: 304 * 8,
+                bits_total: 304 * 8,
+            })
+        );
+    }
+
+    #[test]
+    fn test_matching_changes_by_at_most_one() {
+        let original = noisy_cover();
+        let mut pixels = original.clone();
+        embed(&mut pixels, &[0xA5; 100], "key", PixelMethod::Matching).unwrap();
+        let changed = original
+            .samples()
+            .iter()
+            .zip(pixels.samples())
+            .filter(|(a, b)| a != b)
+            .inspect(|(&a, &b)| assert_eq!((a as i32 - b as i32).abs(), 1))
+            .count();
+        assert!(changed > 0);
+    }
+
+    #[test]
+    fn test_matching_uses_both_directions() {
+        let original = noisy_cover();
+        let mut pixels = original.clone();
+        embed(&mut pixels, &[0x5A; 300], "key", PixelMethod::Matching).unwrap();
+        let deltas: Vec<i32> = original
+            .samples()
+            .iter()
+            .zip(pixels.samples())
+            .map(|(&a, &b)| b as i32 - a as i32)
+            .collect();
+        assert!(deltas.contains(&1));
+        assert!(deltas.contains(&-1));
+    }
+
+    #[test]
+    fn test_matching_clamps_at_bounds() {
+        let mut low = cover(16, 0, 1, 0);
+        embed(&mut low, &[0xFF; 64], "key", PixelMethod::Matching).unwrap();
+        assert!(low.samples().iter().all(|&s| s <= 1));
+        assert_eq!(extract(&low, "key").unwrap(), vec![0xFF; 64]);
+
+        let mut high = cover(8, 2, 3, 255);
+        embed(&mut high, &[0x00; 64], "key", PixelMethod::Matching).unwrap();
+        assert!(high.samples().iter().all(|&s| s >= 254));
+        assert_eq!(extract(&high, "key").unwrap(), vec![0x00; 64]);
+    }
+
+    #[test]
+    fn test_low_bit_depth() {
+        let mut pixels = cover(1, 0, 1, 1);
+        embed(&mut pixels, b"one bit", "key", PixelMethod::Matching).unwrap();
+        assert!(pixels.samples().iter().all(|&s| s <= 1));
+        assert_eq!(extract(&pixels, "key").unwrap(), b"one bit");
+    }
+
+    #[test]
+    fn test_alpha_untouched() {
+        let mut pixels = cover(8, 6, 4, 128);
+        embed(&mut pixels, &[0x33; 200], "key", PixelMethod::Matching).unwrap();
+        assert!(pixels
+            .samples()
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| pixels.is_alpha_sample(*i))
+            .all(|(_, &s)| s == 128));
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let mut pixels = noisy_cover();
+        embed(&mut pixels, b"hidden message", "key", PixelMethod::Matching).unwrap();
+        let other = extract(&pixels, "other key");
+        assert!(other.map_or(true, |payload| payload != b"hidden message"));
+    }
+
+    #[test]
+    fn test_payload_too_large() {
+        let mut pixels = cover(8, 0, 1, 0);
+        let payload = vec![0; capacity(&pixels).unwrap() + 1];
+        assert!(embed(&mut pixels, &payload, "key", PixelMethod::Matching).is_err());
+    }
+
+    #[test]
+    fn test_palette_rejected() {
+        let mut pixels = cover(8, 3, 1, 0);
+        assert!(embed(&mut pixels, b"x", "key", PixelMethod::Matching).is_err());
+    }
+}
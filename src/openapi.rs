@@ -0,0 +1,350 @@
+use crate::auth::Scope;
+use rocket::http::Method;
+use rocket::Route;
+use schemars::gen::{SchemaGenerator, SchemaSettings};
+use schemars::schema::Schema;
+use schemars::JsonSchema;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+
+type SchemaFn = fn(&mut SchemaGenerator) -> Schema;
+
+struct Param {
+    name: &'static str,
+    /// `path` or `query` from the route, or `header`
+    location: &'static str,
+    schema: SchemaFn,
+    description: &'static str,
+}
+
+/// The body of a request or response.
+enum Body {
+    Empty,
+    Json(SchemaFn),
+    /// `multipart/form-data` with an `image` file and these text fields
+    Form(&'static [&'static str]),
+    /// Raw bytes of the given content type
+    Raw(&'static str),
+}
+
+/// What the OpenAPI document says about a route beyond what Rocket knows:
+/// the scope it needs and the shapes of its bodies.
+pub struct Operation {
+    summary: &'static str,
+    scope: Option<Scope>,
+    request: Body,
+    response: Body,
+    /// The schema of failures and how they are told apart from success
+    error: Option<(SchemaFn, &'static str)>,
+    status: u16,
+    params: Vec<Param>,
+    successor: Option<&'static str>,
+}
+
+impl Operation {
+    /// A public operation answering `200` with no body.
+    pub fn new(summary: &'static str) -> Operation {
+        Operation {
+            summary,
+            scope: None,
+            request: Body::Empty,
+            response: Body::Empty,
+            error: None,
+            status: 200,
+            params: vec![],
+            successor: None,
+        }
+    }
+
+    pub fn read(mut self) -> Operation {
+        self.scope = Some(Scope::Read);
+        self
+    }
+
+    pub fn write(mut self) -> Operation {
+        self.scope = Some(Scope::Write);
+        self
+    }
+
+    pub fn json_body<T: JsonSchema>(mut self) -> Operation {
+        self.request = Body::Json(SchemaGenerator::subschema_for::<T>);
+        self
+    }
+
+    /// A multipart body of an `image` file and the given text fields.
+    pub fn form_body(mut self, fields: &'static [&'static str]) -> Operation {
+        self.request = Body::Form(fields);
+        self
+    }
+
+    /// A body of raw bytes of the given content type.
+    pub fn raw_body(mut self, content_type: &'static str) -> Operation {
+        self.request = Body::Raw(content_type);
+        self
+    }
+
+    /// Answers with `T` as JSON, on failure as well as success unless
+    /// [`errors`](Operation::errors) says otherwise.
+    pub fn returns<T: JsonSchema>(mut self) -> Operation {
+        self.response = Body::Json(SchemaGenerator::subschema_for::<T>);
+        self.error = Some((
+            SchemaGenerator::subschema_for::<T>,
+            "Failure, with `status` set to \"error\" and a machine-readable `code`",
+        ));
+        self
+    }
+
+    /// Answers failures with `T` as JSON.
+    pub fn errors<T: JsonSchema>(mut self) -> Operation {
+        self.error = Some((SchemaGenerator::subschema_for::<T>, "Failure, with a machine-readable `code`"));
+        self
+    }
+
+    pub fn returns_raw(mut self, content_type: &'static str) -> Operation {
+        self.response = Body::Raw(content_type);
+        self
+    }
+
+    /// The status of a successful answer, if not `200`.
+    pub fn status(mut self, status: u16) -> Operation {
+        self.status = status;
+        self
+    }
+
+    /// Describes the path or query parameter `name`; query parameters are
+    /// optional when `T` is an `Option`.
+    pub fn param<T: JsonSchema>(mut self, name: &'static str, description: &'static str) -> Operation {
+        self.params.push(Param {
+            name,
+            location: "route",
+            schema: SchemaGenerator::subschema_for::<T>,
+            description,
+        });
+        self
+    }
+
+    /// A request header the operation reads, required unless `T` is an
+    /// `Option`.
+    pub fn header<T: JsonSchema>(mut self, name: &'static str, description: &'static str) -> Operation {
+        self.params.push(Param {
+            name,
+            location: "header",
+            schema: SchemaGenerator::subschema_for::<T>,
+            description,
+        });
+        self
+    }
+
+    /// Marks the operation deprecated in favour of the `successor` path.
+    pub fn deprecated_by(mut self, successor: &'static str) -> Operation {
+        self.successor = Some(successor);
+        self
+    }
+
+    pub fn successor(&self) -> Option<&'static str> {
+        self.successor
+    }
+}
+
+/// A mounted route's method and full URI, query included, which tell it
+/// apart from every other route even where handler names repeat.
+pub type RouteKey = (Method, String);
+
+pub fn route_key(route: &Route) -> RouteKey {
+    (route.method, route.uri.as_str().to_string())
+}
+
+/// Routes paired with what the OpenAPI document says about each, so a
+/// route is mounted and described in one place.
+pub struct Api {
+    base: &'static str,
+    routes: Vec<Route>,
+    operations: HashMap<RouteKey, Operation>,
+}
+
+impl Api {
+    /// Routes to be mounted at `base`.
+    pub fn new(base: &'static str) -> Api {
+        Api { base, routes: vec![], operations: HashMap::new() }
+    }
+
+    /// Adds the routes of one handler, as `routes![handler]` gives them,
+    /// described by `operation`.
+    pub fn route(mut self, routes: Vec<Route>, operation: Operation) -> Api {
+        if let Some(route) = routes.first() {
+            let key = (route.method, format!("{}{}", self.base.trim_end_matches('/'), route.uri.as_str()));
+            let message = format!("{} {} is described twice", key.0, key.1);
+            assert!(self.operations.insert(key, operation).is_none(), "{}", message);
+        }
+        self.routes.extend(routes);
+        self
+    }
+
+    /// The routes to mount at the base, and their operations by
+    /// [`route_key`] once mounted, for [`document`].
+    pub fn into_parts(self) -> (Vec<Route>, HashMap<RouteKey, Operation>) {
+        (self.routes, self.operations)
+    }
+}
+
+/// Builds the OpenAPI document for `routes`, describing each by its entry
+/// in `operations`. CORS preflight routes are left out. Fails naming the
+/// first route without an entry, or the second of two handlers sharing an
+/// operation ID.
+pub fn document<'a>(
+    routes: impl Iterator<Item = &'a Route>,
+    operations: &HashMap<RouteKey, Operation>,
+) -> Result<Value, String> {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    let mut paths = Map::new();
+    let mut names = HashSet::new();
+    for route in routes.filter(|r| r.method != Method::Options) {
+        let name = route.name.as_deref().unwrap_or_default();
+        let operation = operations
+            .get(&route_key(route))
+            .ok_or_else(|| format!("no OpenAPI operation for {} {} ({})", route.method, route.uri, name))?;
+        if !names.insert(name) {
+            return Err(format!("operation ID {} is used twice, by {} {}", name, route.method, route.uri));
+        }
+        let (path, params) = parameters(route);
+        let method = route.method.as_str().to_lowercase();
+        let entry = describe(name, operation, &params, &mut generator)?;
+        let item = paths.entry(path).or_insert_with(|| json!({}));
+        item[method] = entry;
+    }
+
+    Ok(json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "StegaRust API",
+            "description": "Hide, find and strip data in PNG images.",
+            "version": env!("CARGO_PKG_VERSION"),
+        },
+        "paths": paths,
+        "components": {
+            "schemas": json!(generator.take_definitions()),
+            "securitySchemes": {
+                "bearer": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "A token from the `auth` table; `write` tokens may also read.",
+                },
+            },
+        },
+    }))
+}
+
+/// The route's path in OpenAPI form, with its parameters as `(name, in)`.
+fn parameters(route: &Route) -> (String, Vec<(String, &'static str)>) {
+    let mut params = vec![];
+    let segments: Vec<String> = route
+        .uri
+        .path()
+        .split('/')
+        .map(|segment| match segment.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+            Some(name) => {
+                params.push((name.to_string(), "path"));
+                format!("{{{}}}", name)
+            }
+            None => segment.to_string(),
+        })
+        .collect();
+    for field in route.uri.query().into_iter().flat_map(|q| q.split('&')) {
+        if let Some(name) = field.strip_prefix('<').and_then(|s| s.strip_suffix('>')) {
+            params.push((name.to_string(), "query"));
+        }
+    }
+    (segments.join("/"), params)
+}
+
+fn describe(
+    name: &str,
+    operation: &Operation,
+    params: &[(String, &'static str)],
+    generator: &mut SchemaGenerator,
+) -> Result<Value, String> {
+    let mut entry = json!({
+        "operationId": name,
+        "summary": operation.summary,
+    });
+    if let Some(scope) = operation.scope {
+        entry["security"] = json!([{ "bearer": [] }]);
+        entry["x-scope"] = json!(scope);
+    }
+    if let Some(successor) = operation.successor {
+        entry["deprecated"] = Value::from(true);
+        entry["x-successor"] = Value::from(successor);
+    }
+
+    let mut parameters = vec![];
+    let headers = operation.params.iter().filter(|p| p.location == "header");
+    let params = params
+        .iter()
+        .cloned()
+        .chain(headers.map(|p| (p.name.to_string(), p.location)))
+        .collect::<Vec<_>>();
+    for (param, location) in &params {
+        let described = operation.params.iter().find(|p| p.name == param);
+        let (schema, optional, description) = match described {
+            Some(p) => {
+                // Parameters say whether they are optional with `required`
+                // rather than the `nullable` schemars gives an `Option`.
+                let mut schema = json!((p.schema)(generator));
+                let nullable = schema.as_object_mut().and_then(|s| s.remove("nullable"));
+                (schema, nullable.is_some(), p.description)
+            }
+            None => (json!({ "type": "string" }), true, ""),
+        };
+        let mut parameter = json!({
+            "name": param,
+            "in": location,
+            "required": *location == "path" || !optional,
+            "schema": schema,
+        });
+        if !description.is_empty() {
+            parameter["description"] = Value::from(description);
+        }
+        parameters.push(parameter);
+    }
+    if let Some(unknown) = operation.params.iter().find(|p| !params.iter().any(|(n, _)| n == p.name)) {
+        return Err(format!("{} describes a parameter it does not have -: {}", name, unknown.name));
+    }
+    if !parameters.is_empty() {
+        entry["parameters"] = Value::from(parameters);
+    }
+
+    if let Some(content) = content(&operation.request, generator) {
+        entry["requestBody"] = json!({ "required": true, "content": content });
+    }
+    let mut responses = json!({});
+    let mut success = json!({ "description": "Success" });
+    if let Some(content) = content(&operation.response, generator) {
+        success["content"] = content;
+    }
+    if let Some((error, description)) = operation.error {
+        responses["default"] = json!({
+            "description": description,
+            "content": { "application/json": { "schema": error(generator) } },
+        });
+    }
+    responses[operation.status.to_string()] = success;
+    entry["responses"] = responses;
+    Ok(entry)
+}
+
+fn content(body: &Body, generator: &mut SchemaGenerator) -> Option<Value> {
+    let (content_type, schema) = match body {
+        Body::Empty => return None,
+        Body::Json(schema) => ("application/json", json!(schema(generator))),
+        Body::Form(fields) => {
+            let mut properties = Map::new();
+            properties.insert("image".to_string(), json!({ "type": "string", "format": "binary" }));
+            for field in fields.iter() {
+                properties.insert(field.to_string(), json!({ "type": "string" }));
+            }
+            ("multipart/form-data", json!({ "type": "object", "properties": properties, "required": ["image"] }))
+        }
+        Body::Raw(content_type) => (*content_type, json!({ "type": "string", "format": "binary" })),
+    };
+    Some(json!({ content_type: { "schema": schema } }))
+}
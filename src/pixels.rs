@@ -0,0 +1,429 @@
+use crate::png::Png;
+use crate::Error;
+use miniz_oxide::deflate::compress_to_vec_zlib;
+use miniz_oxide::inflate::{decompress_to_vec_zlib_with_limit, TINFLStatus};
+
+/// Bytes an image may take up once decoded when the caller sets no limit
+/// of its own: room for an 8192×8192 RGBA16 image. The limit keeps a header
+/// claiming huge dimensions from making a small zlib bomb inflate into
+/// gigabytes.
+pub const DEFAULT_DECODE_LIMIT: usize = 512 << 20;
+
+/// Decoded image samples of a non-interlaced PNG.
+///
+/// Every channel of every pixel is stored as one `u16` sample in row-major
+/// order, whatever the bit depth of the source image, so callers can work on
+/// 1, 2, 4, 8 and 16 bit images alike.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pixels {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: u8,
+    samples: Vec<u16>,
+}
+
+impl TryFrom<&Png> for Pixels {
+    type Error = Error;
+
+    fn try_from(png: &Png) -> Result<Self, Self::Error> {
+        Pixels::decode(png, DEFAULT_DECODE_LIMIT)
+    }
+}
+
+impl Pixels {
+    /// Decodes the image data of `png`, refusing images whose samples would
+    /// take up more than `limit` bytes before anything is inflated.
+    pub fn decode(png: &Png, limit: usize) -> Result<Pixels, Error> {
+        let ihdr = png
+            .chunk_by_type("IHDR")
+            .ok_or_else(|| Error::ChunkNotFound("IHDR".to_string()))?;
+        let header = ihdr.data();
+        if header.len() != 13 {
+            return Err(Error::InvalidImage("IHDR must be 13 bytes".to_string()));
+        }
+
+        let width = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        let height = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
+        let bit_depth = header[8];
+        let color_type = header[9];
+        if header[12] != 0 {
+            return Err(Error::Unsupported(
+                "interlaced images are not supported".to_string(),
+            ));
+        }
+        let channels = Self::validate(width, height, bit_depth, color_type)?;
+        let decoded = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(channels * std::mem::size_of::<u16>()))
+            .ok_or_else(|| Error::Unsupported("image is too large".to_string()))?;
+        if decoded > limit {
+            return Err(Error::Unsupported(format!(
+                "image would decode to {} bytes, over the limit of {}",
+                decoded, limit
+            )));
+        }
+
+        let compressed: Vec<u8> = png
+            .chunks()
+            .iter()
+            .filter(|c| c.chunk_type().to_string() == "IDAT")
+            .flat_map(|c| c.data().iter().copied())
+            .collect();
+        let stride = Self::stride(width, channels, bit_depth)?;
+        let expected = (stride + 1)
+            .checked_mul(height as usize)
+            .ok_or_else(|| Error::Unsupported("image is too large".to_string()))?;
+
+        // Never inflate more than the header says the image holds, so a small
+        // hostile IDAT cannot expand into an unbounded allocation.
+        let raw = match decompress_to_vec_zlib_with_limit(&compressed, expected) {
+            Ok(raw) => raw,
+            Err(e) if e.status == TINFLStatus::HasMoreOutput => e.output,
+            Err(e) => {
+                return Err(Error::InvalidImage(format!(
+                    "unable to inflate image data: {:?}",
+                    e.status
+                )))
+            }
+        };
+        if raw.len() < expected {
+            return Err(Error::InvalidImage("image data is truncated".to_string()));
+        }
+
+        let bpp = usize::max(1, channels * bit_depth as usize / 8);
+        let rows = unfilter(&raw[..expected], stride, bpp)?;
+
+        let per_row = width as usize * channels;
+        let mut samples = Vec::with_capacity(per_row * height as usize);
+        for row in rows.chunks(stride) {
+            samples.extend(unpack_row(row, per_row, bit_depth));
+        }
+
+        Ok(Pixels {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            samples,
+        })
+    }
+
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: u8,
+        samples: Vec<u16>,
+    ) -> Result<Pixels, Error> {
+        let channels = Self::validate(width, height, bit_depth, color_type)?;
+        if samples.len() != width as usize * height as usize * channels {
+            return Err(Error::InvalidArgument(
+                "sample count does not match image dimensions".to_string(),
+            ));
+        }
+        let max = (1u32 << bit_depth) - 1;
+        if samples.iter().any(|&s| s as u32 > max) {
+            return Err(Error::InvalidArgument(
+                "sample value exceeds bit depth".to_string(),
+            ));
+        }
+        Ok(Pixels {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            samples,
+        })
+    }
+
+    fn validate(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Result<usize, Error> {
+        if width == 0 || height == 0 {
+            return Err(Error::InvalidImage(
+                "image dimensions must be non-zero".to_string(),
+            ));
+        }
+        let (channels, depths): (usize, &[u8]) = match color_type {
+            0 => (1, &[1, 2, 4, 8, 16]),
+            2 => (3, &[8, 16]),
+            3 => (1, &[1, 2, 4, 8]),
+            4 => (2, &[8, 16]),
+            6 => (4, &[8, 16]),
+            _ => {
+                return Err(Error::InvalidImage(format!(
+                    "invalid color type {}",
+                    color_type
+                )))
+            }
+        };
+        if !depths.contains(&bit_depth) {
+            return Err(Error::InvalidImage(format!(
+                "invalid bit depth {} for color type {}",
+                bit_depth, color_type
+            )));
+        }
+        Ok(channels)
+    }
+
+    fn stride(width: u32, channels: usize, bit_depth: u8) -> Result<usize, Error> {
+        (width as usize)
+            .checked_mul(channels * bit_depth as usize)
+            .map(|bits| bits.div_ceil(8))
+            .ok_or_else(|| Error::Unsupported("image is too large".to_string()))
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn color_type(&self) -> u8 {
+        self.color_type
+    }
+
+    pub fn channels(&self) -> usize {
+        self.samples.len() / (self.width as usize * self.height as usize)
+    }
+
+    /// Largest value a sample can hold at this bit depth.
+    pub fn max_sample(&self) -> u16 {
+        ((1u32 << self.bit_depth) - 1) as u16
+    }
+
+    pub fn is_palette(&self) -> bool {
+        self.color_type == 3
+    }
+
+    pub fn has_alpha(&self) -> bool {
+        self.color_type == 4 || self.color_type == 6
+    }
+
+    /// Whether the sample at `index` belongs to the alpha channel.
+    pub fn is_alpha_sample(&self, index: usize) -> bool {
+        let channels = self.channels();
+        self.has_alpha() && index % channels == channels - 1
+    }
+
+    pub fn samples(&self) -> &[u16] {
+        &self.samples
+    }
+
+    pub fn samples_mut(&mut self) -> &mut [u16] {
+        &mut self.samples
+    }
+
+    /// The 13 byte IHDR payload describing these pixels.
+    pub fn header_bytes(&self) -> Vec<u8> {
+        let mut header = vec![];
+        header.extend(self.width.to_be_bytes());
+        header.extend(self.height.to_be_bytes());
+        header.extend([self.bit_depth, self.color_type, 0, 0, 0]);
+        header
+    }
+
+    /// The zlib stream to store in IDAT, using filter type None on every row.
+    pub fn image_data(&self) -> Vec<u8> {
+        let per_row = self.width as usize * self.channels();
+        let mut raw = vec![];
+        for row in self.samples.chunks(per_row) {
+            raw.push(0);
+            raw.extend(pack_row(row, self.bit_depth));
+        }
+        compress_to_vec_zlib(&raw, 6)
+    }
+}
+
+fn unfilter(raw: &[u8], stride: usize, bpp: usize) -> Result<Vec<u8>, Error> {
+    let mut out: Vec<u8> = Vec::with_capacity(raw.len());
+    for line in raw.chunks(stride + 1) {
+        let filter = line[0];
+        let start = out.len();
+        for x in 0..stride {
+            let a = if x >= bpp { out[start + x - bpp] } else { 0 };
+            let b = if start >= stride {
+                out[start - stride + x]
+            } else {
+                0
+            };
+            let c = if x >= bpp && start >= stride {
+                out[start - stride + x - bpp]
+            } else {
+                0
+            };
+            let value = line[1 + x];
+            out.push(match filter {
+                0 => value,
+                1 => value.wrapping_add(a),
+                2 => value.wrapping_add(b),
+                3 => value.wrapping_add(((a as u16 + b as u16) / 2) as u8),
+                4 => value.wrapping_add(paeth(a, b, c)),
+                _ => {
+                    return Err(Error::InvalidImage(format!(
+                        "invalid filter type {}",
+                        filter
+                    )))
+                }
+            });
+        }
+    }
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn unpack_row(row: &[u8], count: usize, bit_depth: u8) -> Vec<u16> {
+    match bit_depth {
+        16 => row
+            .chunks(2)
+            .take(count)
+            .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+            .collect(),
+        8 => row.iter().take(count).map(|&b| b as u16).collect(),
+        _ => {
+            let per_byte = 8 / bit_depth as usize;
+            let mask = (1u8 << bit_depth) - 1;
+            (0..count)
+                .map(|i| {
+                    let shift = 8 - bit_depth as usize * (i % per_byte + 1);
+                    ((row[i / per_byte] >> shift) & mask) as u16
+                })
+                .collect()
+        }
+    }
+}
+
+fn pack_row(row: &[u16], bit_depth: u8) -> Vec<u8> {
+    match bit_depth {
+        16 => row.iter().flat_map(|s| s.to_be_bytes()).collect(),
+        8 => row.iter().map(|&s| s as u8).collect(),
+        _ => {
+            let per_byte = 8 / bit_depth as usize;
+            let mut packed = vec![0u8; row.len().div_ceil(per_byte)];
+            for (i, &sample) in row.iter().enumerate() {
+                let shift = 8 - bit_depth as usize * (i % per_byte + 1);
+                packed[i / per_byte] |= (sample as u8) << shift;
+            }
+            packed
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn gradient(width: u32, height: u32, bit_depth: u8, color_type: u8, channels: usize) -> Pixels {
+        let max = (1u32 << bit_depth) - 1;
+        let samples = (0..width as usize * height as usize * channels)
+            .map(|i| (i as u32 * 7 % (max + 1)) as u16)
+            .collect();
+        Pixels::new(width, height, bit_depth, color_type, samples).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_all_bit_depths() {
+        let cases = [
+            (0, 1, 1),
+            (0, 2, 1),
+            (0, 4, 1),
+            (0, 8, 1),
+            (0, 16, 1),
+            (2, 8, 3),
+            (2, 16, 3),
+            (4, 8, 2),
+            (6, 16, 4),
+        ];
+        for (color_type, bit_depth, channels) in cases {
+            let pixels = gradient(13, 5, bit_depth, color_type, channels);
+            let png = Png::from_pixels(&pixels);
+            let bytes = png.as_bytes();
+            let decoded = Png::try_from(bytes.as_slice()).unwrap().pixels().unwrap();
+            assert_eq!(decoded, pixels);
+        }
+    }
+
+    #[test]
+    fn test_invalid_bit_depth() {
+        assert!(Pixels::new(1, 1, 4, 2, vec![0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_sample_out_of_range() {
+        assert!(Pixels::new(1, 1, 2, 0, vec![4]).is_err());
+    }
+
+    #[test]
+    fn test_unfilter_paeth_and_average() {
+        // Two one-byte rows: the second uses Average, then a Paeth row.
+        let raw = [0, 10, 3, 4, 4, 2];
+        let rows = unfilter(&raw, 1, 1).unwrap();
+        assert_eq!(rows, vec![10, 9, 11]);
+    }
+
+    fn hostile_image(width: u32, height: u32, raw: &[u8]) -> Png {
+        let mut header = vec![];
+        header.extend(width.to_be_bytes());
+        header.extend(height.to_be_bytes());
+        header.extend([16, 6, 0, 0, 0]);
+        let mut chunks = Png::from_pixels(&gradient(1, 1, 8, 0, 1)).into_chunks();
+        chunks[0] = Chunk::new(ChunkType::from_str("IHDR").unwrap(), header);
+        chunks[1] = Chunk::new(
+            ChunkType::from_str("IDAT").unwrap(),
+            compress_to_vec_zlib(raw, 6),
+        );
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_hostile_dimensions() {
+        assert!(hostile_image(u32::MAX, u32::MAX, &[0; 16]).pixels().is_err());
+        assert!(hostile_image(65535, 65535, &[0; 16]).pixels().is_err());
+    }
+
+    #[test]
+    fn test_decode_limit() {
+        let bomb = hostile_image(65535, 65535, &vec![0; 1 << 20]);
+        let refused = bomb.pixels().unwrap_err();
+        assert!(refused.to_string().contains("over the limit"), "{}", refused);
+
+        let png = Png::from_pixels(&gradient(4, 4, 8, 2, 3));
+        assert!(Pixels::decode(&png, 4 * 4 * 3 * 2 - 1).is_err());
+        assert!(Pixels::decode(&png, 4 * 4 * 3 * 2).is_ok());
+    }
+
+    #[test]
+    fn test_inflate_is_bounded_by_header() {
+        let pixels = hostile_image(1, 1, &vec![0; 1 << 20]).pixels().unwrap();
+        assert_eq!(pixels.samples(), &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_alpha_samples() {
+        let pixels = gradient(2, 1, 8, 6, 4);
+        assert!(pixels.is_alpha_sample(3));
+        assert!(!pixels.is_alpha_sample(2));
+        assert!(pixels.is_alpha_sample(7));
+    }
+}
@@ -1,44 +1,218 @@
 use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::pixels::Pixels;
 use crate::Error;
+use serde::Serialize;
 use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 pub struct Png {
     chunks: Vec<Chunk>,
+    /// Bytes stored after IEND, which decoders ignore.
+    overlay: Vec<u8>,
 }
 
 impl TryFrom<&[u8]> for Png {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if !value.starts_with(&Self::STANDARD_HEADER) {
+            return Err(Error::InvalidSignature);
+        }
         let mut chunks = vec![];
-        let mut iter = value.iter();
+        let mut rest = &value[Self::STANDARD_HEADER.len()..];
+        while !rest.is_empty() {
+            let chunk = Chunk::try_from(rest)?;
+            rest = &rest[chunk.length() as usize + 12..];
+            let is_iend = chunk.chunk_type().bytes() == *b"IEND";
+            chunks.push(chunk);
+            if is_iend {
+                break;
+            }
+        }
+        let mut png = Png::from_chunks(chunks);
+        png.overlay = rest.to_vec();
+        Ok(png)
+    }
+}
 
-        let header_bytes: Vec<u8> = iter.by_ref().take(8).copied().collect();
-        if Self::STANDARD_HEADER != header_bytes.as_slice() {
-            return Err(Error::from("Invalid header"));
+/// Where to put a chunk inserted into an image.
+///
+/// Whatever is asked for, IHDR stays first and IEND stays last: a position
+/// before IHDR resolves to just after it, and one after IEND to just before it.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Position {
+    /// Where the inserted chunk's type belongs: text and other chunks the
+    /// PNG specification orders before the image data go before IDAT (and
+    /// before PLTE where it asks for that), anything else just before IEND.
+    #[default]
+    Auto,
+    /// Just before IEND.
+    End,
+    /// At this chunk index.
+    Index(usize),
+    /// Before the first chunk of this type, e.g. `before:IDAT`.
+    Before(String),
+    /// After the first chunk of this type, e.g. `after:IHDR`.
+    After(String),
+}
+
+/// Chunk types that must come before PLTE, and so before IDAT too.
+const BEFORE_PLTE: [&[u8; 4]; 8] = [
+    b"cHRM", b"gAMA", b"iCCP", b"sBIT", b"sRGB", b"cICP", b"mDCV", b"cLLI",
+];
+
+/// Chunk types that must, or for text are best read, before IDAT.
+const BEFORE_IDAT: [&[u8; 4]; 10] = [
+    b"PLTE", b"bKGD", b"hIST", b"tRNS", b"pHYs", b"sPLT", b"eXIf", b"tEXt", b"zTXt", b"iTXt",
+];
+
+impl Position {
+    /// Whether a chunk of type `inserted` placed at this position goes right
+    /// before `current`, the chunk at `index`, which follows `previous`.
+    /// Walking the chunks in order, the first match is where the new chunk
+    /// belongs.
+    pub fn inserts_before(
+        &self,
+        inserted: &ChunkType,
+        index: usize,
+        current: &ChunkType,
+        previous: Option<&ChunkType>,
+    ) -> bool {
+        if current.bytes() == *b"IEND" {
+            return true;
         }
-        while iter.len() >= 12 {
-            let first4: [u8; 4] = iter
-                .clone()
-                .take(4)
-                .copied()
-                .collect::<Vec<u8>>()
-                .as_slice()
-                .try_into()?;
-            let length = u32::from_be_bytes(first4);
-
-            let chunk = Chunk::try_from(
-                iter.by_ref()
-                    .take(length as usize + 12)
-                    .copied()
-                    .collect::<Vec<u8>>()
-                    .as_slice(),
-            )?;
-            chunks.push(chunk);
+        if current.bytes() == *b"IHDR" {
+            return false;
+        }
+        match self {
+            Position::Auto => {
+                let inserted = inserted.bytes();
+                let current = current.bytes();
+                if BEFORE_PLTE.contains(&&inserted) {
+                    current == *b"PLTE" || current == *b"IDAT"
+                } else {
+                    BEFORE_IDAT.contains(&&inserted) && current == *b"IDAT"
+                }
+            }
+            Position::End => false,
+            Position::Index(at) => index >= *at,
+            Position::Before(chunk_type) => current.to_string() == *chunk_type,
+            Position::After(chunk_type) => {
+                previous.is_some_and(|previous| previous.to_string() == *chunk_type)
+            }
+        }
+    }
+
+    /// Checks that this position exists in an image with `chunk_types`.
+    pub fn check(&self, chunk_types: &[String]) -> Result<(), Error> {
+        match self {
+            Position::Auto | Position::End => Ok(()),
+            Position::Index(index) if *index > chunk_types.len() => Err(Error::InvalidArgument(
+                format!("chunk index {} out of range", index),
+            )),
+            Position::Index(_) => Ok(()),
+            Position::Before(chunk_type) | Position::After(chunk_type) => {
+                if chunk_types.contains(chunk_type) {
+                    Ok(())
+                } else {
+                    Err(Error::ChunkNotFound(chunk_type.clone()))
+                }
+            }
         }
-        Ok(Png::from_chunks(chunks))
     }
 }
+
+impl FromStr for Position {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => return Ok(Position::Auto),
+            "end" => return Ok(Position::End),
+            _ => {}
+        }
+        if let Ok(index) = s.parse() {
+            return Ok(Position::Index(index));
+        }
+        let invalid = || {
+            Error::InvalidArgument(format!(
+                "invalid position {:?}, expected auto, end, an index, before:TYPE or after:TYPE",
+                s
+            ))
+        };
+        let (relation, chunk_type) = s.split_once(':').ok_or_else(invalid)?;
+        ChunkType::from_str(chunk_type)?;
+        match relation {
+            "before" => Ok(Position::Before(chunk_type.to_string())),
+            "after" => Ok(Position::After(chunk_type.to_string())),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Which of several chunks of the same type to act on, counting from 0 in
+/// file order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Occurrence {
+    #[default]
+    First,
+    Nth(usize),
+    /// Occurrences `start` up to but not including `end`.
+    Range(usize, usize),
+    All,
+}
+
+impl Occurrence {
+    pub fn selects(&self, occurrence: usize) -> bool {
+        match self {
+            Occurrence::First => occurrence == 0,
+            Occurrence::Nth(n) => occurrence == *n,
+            Occurrence::Range(start, end) => (*start..*end).contains(&occurrence),
+            Occurrence::All => true,
+        }
+    }
+}
+
+impl FromStr for Occurrence {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            Error::InvalidArgument(format!(
+                "invalid occurrence {:?}, expected first, all, N or START..END",
+                s
+            ))
+        };
+        match s {
+            "first" => return Ok(Occurrence::First),
+            "all" => return Ok(Occurrence::All),
+            _ => {}
+        }
+        if let Some((start, end)) = s.split_once("..") {
+            let start = start.parse().map_err(|_| invalid())?;
+            let end = end.parse().map_err(|_| invalid())?;
+            if start > end {
+                return Err(Error::InvalidArgument(format!(
+                    "invalid occurrence {:?}, the range starts after it ends",
+                    s
+                )));
+            }
+            return Ok(Occurrence::Range(start, end));
+        }
+        s.parse().map(Occurrence::Nth).map_err(|_| invalid())
+    }
+}
+
+/// Where a chunk sits in an image, for listing chunks without their data.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct ChunkInfo {
+    pub index: usize,
+    pub chunk_type: String,
+    pub size: u32,
+}
+
 impl Display for Png {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:?}", self.as_bytes())
@@ -47,15 +221,117 @@ impl Display for Png {
 
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+    const IDAT_CHUNK_SIZE: usize = 1 << 20;
+
+    pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
+        Png {
+            chunks,
+            overlay: vec![],
+        }
+    }
 
-    fn from_chunks(chunks: Vec<Chunk>) -> Png {
-        Png { chunks }
+    pub fn into_chunks(self) -> Vec<Chunk> {
+        self.chunks
+    }
+
+    /// Parses `value` up to and including the IEND chunk, returning the image
+    /// without its overlay together with the bytes that follow IEND.
+    pub fn split_trailing(value: &[u8]) -> Result<(Png, &[u8]), Error> {
+        let mut png = Png::try_from(value)?;
+        let end = value.len() - std::mem::take(&mut png.overlay).len();
+        Ok((png, &value[end..]))
+    }
+
+    /// Data appended after IEND.
+    pub fn overlay(&self) -> &[u8] {
+        &self.overlay
+    }
+
+    /// Replaces the data after IEND; an empty overlay removes it.
+    pub fn set_overlay(&mut self, overlay: Vec<u8>) {
+        self.overlay = overlay;
+    }
+
+    /// Builds a minimal IHDR, IDAT, IEND image from decoded pixels.
+    pub fn from_pixels(pixels: &Pixels) -> Png {
+        let mut chunks = vec![Self::new_chunk("IHDR", pixels.header_bytes())];
+        chunks.extend(Self::image_data_chunks(pixels));
+        chunks.push(Self::new_chunk("IEND", vec![]));
+        Png::from_chunks(chunks)
+    }
+
+    fn new_chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn image_data_chunks(pixels: &Pixels) -> Vec<Chunk> {
+        pixels
+            .image_data()
+            .chunks(Self::IDAT_CHUNK_SIZE)
+            .map(|data| Self::new_chunk("IDAT", data.to_vec()))
+            .collect()
     }
 
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
 
+    /// Inserts `chunk` at `position`, returning the index it ended up at.
+    pub fn insert_chunk(&mut self, chunk: Chunk, position: &Position) -> Result<usize, Error> {
+        self.check_position(position)?;
+        let index = self.resolve(chunk.chunk_type(), position);
+        self.chunks.insert(index, chunk);
+        Ok(index)
+    }
+
+    /// Swaps the chunk at `index` for `chunk`, returning the old one.
+    pub fn replace_chunk(&mut self, index: usize, chunk: Chunk) -> Result<Chunk, Error> {
+        let slot = self
+            .chunks
+            .get_mut(index)
+            .ok_or_else(|| Self::out_of_range(index))?;
+        Ok(std::mem::replace(slot, chunk))
+    }
+
+    /// Moves the chunk at `index` to `position`, returning its new index.
+    pub fn move_chunk(&mut self, index: usize, position: &Position) -> Result<usize, Error> {
+        if index >= self.chunks.len() {
+            return Err(Self::out_of_range(index));
+        }
+        let chunk = self.chunks.remove(index);
+        if let Err(e) = self.check_position(position) {
+            self.chunks.insert(index, chunk);
+            return Err(e);
+        }
+        let to = self.resolve(chunk.chunk_type(), position);
+        self.chunks.insert(to, chunk);
+        Ok(to)
+    }
+
+    fn resolve(&self, inserted: &ChunkType, position: &Position) -> usize {
+        let mut previous = None;
+        for (index, chunk) in self.chunks.iter().enumerate() {
+            if position.inserts_before(inserted, index, chunk.chunk_type(), previous) {
+                return index;
+            }
+            previous = Some(chunk.chunk_type());
+        }
+        self.chunks.len()
+    }
+
+    fn check_position(&self, position: &Position) -> Result<(), Error> {
+        let chunk_types: Vec<String> = self
+            .chunks
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        position.check(&chunk_types)
+    }
+
+    fn out_of_range(index: usize) -> Error {
+        Error::InvalidArgument(format!("chunk index {} out of range", index))
+    }
+
     pub fn remove_chunk(&mut self, chunk_type: &str) -> Result<Chunk, Error> {
         let searched = self
             .chunks
@@ -64,7 +340,7 @@ impl Png {
         if let Some(position) = searched {
             Ok(self.chunks.remove(position))
         } else {
-            Err(Error::from("chunk not found"))
+            Err(Error::ChunkNotFound(chunk_type.to_string()))
         }
     }
 
@@ -82,12 +358,77 @@ impl Png {
             .find(|c| c.chunk_type().to_string() == chunk_type)
     }
 
+    /// Every chunk of `chunk_type` with its index in the image.
+    pub fn chunks_by_type<'a>(
+        &'a self,
+        chunk_type: &'a str,
+    ) -> impl Iterator<Item = (usize, &'a Chunk)> + 'a {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(move |(_, c)| c.chunk_type().to_string() == chunk_type)
+    }
+
+    /// Removes the selected occurrences of `chunk_type`, returning them in
+    /// file order.
+    pub fn remove_chunks(
+        &mut self,
+        chunk_type: &str,
+        occurrence: &Occurrence,
+    ) -> Result<Vec<Chunk>, Error> {
+        let mut seen = 0;
+        let mut removed = vec![];
+        let mut kept = vec![];
+        for chunk in std::mem::take(&mut self.chunks) {
+            if chunk.chunk_type().to_string() == chunk_type {
+                seen += 1;
+                if occurrence.selects(seen - 1) {
+                    removed.push(chunk);
+                    continue;
+                }
+            }
+            kept.push(chunk);
+        }
+        self.chunks = kept;
+        if removed.is_empty() {
+            return Err(Error::ChunkNotFound(chunk_type.to_string()));
+        }
+        Ok(removed)
+    }
+
+    pub fn pixels(&self) -> Result<Pixels, Error> {
+        Pixels::try_from(self)
+    }
+
+    /// Re-encodes the image data from `pixels`, replacing every IDAT chunk
+    /// in place and leaving all other chunks untouched.
+    pub fn set_pixels(&mut self, pixels: &Pixels) -> Result<(), Error> {
+        let is_idat = |c: &Chunk| c.chunk_type().to_string() == "IDAT";
+        let position = self
+            .chunks
+            .iter()
+            .position(is_idat)
+            .ok_or_else(|| Error::ChunkNotFound("IDAT".to_string()))?;
+        let ihdr = self
+            .chunks
+            .iter()
+            .position(|c| c.chunk_type().to_string() == "IHDR")
+            .ok_or_else(|| Error::ChunkNotFound("IHDR".to_string()))?;
+
+        self.chunks[ihdr] = Self::new_chunk("IHDR", pixels.header_bytes());
+        self.chunks.retain(|c| !is_idat(c));
+        self.chunks
+            .splice(position..position, Self::image_data_chunks(pixels));
+        Ok(())
+    }
+
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut result_bytes = vec![];
         result_bytes.extend(self.header());
         for chunk in &self.chunks {
             result_bytes.extend(chunk.as_bytes());
         }
+        result_bytes.extend(&self.overlay);
         result_bytes
     }
 }
@@ -100,13 +441,11 @@ mod tests {
     use std::convert::TryFrom;
 
     fn testing_chunks() -> Vec<Chunk> {
-        let mut chunks = Vec::new();
-
-        chunks.push(chunk_from_strings("FrSt", "I am the first chunk").unwrap());
-        chunks.push(chunk_from_strings("miDl", "I am another chunk").unwrap());
-        chunks.push(chunk_from_strings("LASt", "I am the last chunk").unwrap());
-
-        chunks
+        vec![
+            chunk_from_strings("FrSt", "I am the first chunk").unwrap(),
+            chunk_from_strings("miDl", "I am another chunk").unwrap(),
+            chunk_from_strings("LASt", "I am the last chunk").unwrap(),
+        ]
     }
 
     fn testing_png() -> Png {
@@ -222,12 +561,224 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    fn image_with_text() -> Png {
+        let pixels = Pixels::new(2, 2, 8, 0, vec![0; 4]).unwrap();
+        let mut png = Png::from_pixels(&pixels);
+        png.insert_chunk(chunk_from_strings("tEXt", "a").unwrap(), &Position::End)
+            .unwrap();
+        png
+    }
+
+    fn types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_chunk() {
+        let mut png = image_with_text();
+        assert_eq!(types(&png), vec!["IHDR", "IDAT", "tEXt", "IEND"]);
+
+        let at = Position::from_str("before:IDAT").unwrap();
+        assert_eq!(png.insert_chunk(chunk_from_strings("ruSt", "b").unwrap(), &at).unwrap(), 1);
+        let at = Position::from_str("after:IDAT").unwrap();
+        png.insert_chunk(chunk_from_strings("ruSt", "c").unwrap(), &at).unwrap();
+        assert_eq!(types(&png), vec!["IHDR", "ruSt", "IDAT", "ruSt", "tEXt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_keeps_ihdr_first_and_iend_last() {
+        let mut png = image_with_text();
+        let first = png
+            .insert_chunk(chunk_from_strings("ruSt", "a").unwrap(), &Position::Index(0))
+            .unwrap();
+        assert_eq!(first, 1);
+        let last = png
+            .insert_chunk(chunk_from_strings("ruSt", "b").unwrap(), &Position::Index(5))
+            .unwrap();
+        assert_eq!(last, 4);
+        let after_end = Position::After("IEND".to_string());
+        png.insert_chunk(chunk_from_strings("ruSt", "c").unwrap(), &after_end)
+            .unwrap();
+        assert_eq!(types(&png).last().unwrap(), "IEND");
+
+        assert!(png.insert_chunk(chunk_from_strings("ruSt", "d").unwrap(), &Position::Index(99)).is_err());
+        let missing = Position::Before("zzZz".to_string());
+        assert!(png.insert_chunk(chunk_from_strings("ruSt", "d").unwrap(), &missing).is_err());
+    }
+
+    #[test]
+    fn test_insert_auto() {
+        let mut png = image_with_text();
+        png.insert_chunk(chunk_from_strings("zTXt", "z").unwrap(), &Position::Auto).unwrap();
+        png.insert_chunk(chunk_from_strings("ruSt", "r").unwrap(), &Position::Auto).unwrap();
+        assert_eq!(types(&png), vec!["IHDR", "zTXt", "IDAT", "tEXt", "ruSt", "IEND"]);
+
+        png.insert_chunk(chunk_from_strings("PLTE", "p").unwrap(), &Position::Auto).unwrap();
+        png.insert_chunk(chunk_from_strings("gAMA", "g").unwrap(), &Position::Auto).unwrap();
+        png.insert_chunk(chunk_from_strings("bKGD", "b").unwrap(), &Position::Auto).unwrap();
+        assert_eq!(
+            types(&png),
+            vec!["IHDR", "zTXt", "gAMA", "PLTE", "bKGD", "IDAT", "tEXt", "ruSt", "IEND"]
+        );
+
+        let to = png.move_chunk(6, &Position::Auto).unwrap();
+        assert_eq!(to, 5);
+        assert_eq!(types(&png)[5..7], ["tEXt", "IDAT"]);
+    }
+
+    #[test]
+    fn test_replace_and_move_chunk() {
+        let mut png = image_with_text();
+        let old = png
+            .replace_chunk(2, chunk_from_strings("tEXt", "new").unwrap())
+            .unwrap();
+        assert_eq!(old.data(), b"a");
+        assert_eq!(png.chunks()[2].data(), b"new");
+        assert!(png.replace_chunk(9, chunk_from_strings("tEXt", "x").unwrap()).is_err());
+
+        let to = png.move_chunk(2, &Position::Before("IDAT".to_string())).unwrap();
+        assert_eq!(to, 1);
+        assert_eq!(types(&png), vec!["IHDR", "tEXt", "IDAT", "IEND"]);
+
+        assert!(png.move_chunk(1, &Position::Before("zzZz".to_string())).is_err());
+        assert_eq!(types(&png), vec!["IHDR", "tEXt", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_position_from_str() {
+        assert_eq!(Position::from_str("auto").unwrap(), Position::Auto);
+        assert_eq!(Position::default(), Position::Auto);
+        assert_eq!(Position::from_str("end").unwrap(), Position::End);
+        assert_eq!(Position::from_str("3").unwrap(), Position::Index(3));
+        assert_eq!(
+            Position::from_str("after:IHDR").unwrap(),
+            Position::After("IHDR".to_string())
+        );
+        assert!(Position::from_str("beside:IHDR").is_err());
+        assert!(Position::from_str("before:ID").is_err());
+        assert!(Position::from_str("").is_err());
+    }
+
+    #[test]
+    fn test_chunks_by_type() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "again").unwrap());
+        let found: Vec<usize> = png.chunks_by_type("miDl").map(|(i, _)| i).collect();
+        assert_eq!(found, vec![1, 3]);
+        assert_eq!(png.chunks_by_type("zzZz").count(), 0);
+    }
+
+    #[test]
+    fn test_remove_chunks() {
+        let mut png = testing_png();
+        for message in ["a", "b", "c", "d"] {
+            png.append_chunk(chunk_from_strings("TeSt", message).unwrap());
+        }
+        let removed = png.remove_chunks("TeSt", &Occurrence::Range(1, 3)).unwrap();
+        assert_eq!(removed[0].data(), b"b");
+        assert_eq!(removed[1].data(), b"c");
+
+        let removed = png.remove_chunks("TeSt", &Occurrence::Nth(1)).unwrap();
+        assert_eq!(removed[0].data(), b"d");
+        assert!(png.remove_chunks("TeSt", &Occurrence::Nth(1)).is_err());
+
+        png.remove_chunks("TeSt", &Occurrence::All).unwrap();
+        assert_eq!(png.chunks_by_type("TeSt").count(), 0);
+        assert_eq!(png.chunks().len(), 3);
+    }
+
+    #[test]
+    fn test_occurrence_from_str() {
+        assert_eq!(Occurrence::from_str("first").unwrap(), Occurrence::First);
+        assert_eq!(Occurrence::from_str("all").unwrap(), Occurrence::All);
+        assert_eq!(Occurrence::from_str("2").unwrap(), Occurrence::Nth(2));
+        assert_eq!(Occurrence::from_str("1..4").unwrap(), Occurrence::Range(1, 4));
+        assert!(Occurrence::from_str("1..").is_err());
+        assert!(matches!(Occurrence::from_str("4..1"), Err(Error::InvalidArgument(_))));
+        assert!(Occurrence::from_str("last").is_err());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_pixels_from_image_file() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let pixels = png.pixels().unwrap();
+        assert_eq!(pixels.width(), 50);
+        assert_eq!(pixels.height(), 50);
+        assert_eq!(pixels.channels(), 4);
+        assert_eq!(pixels.samples().len(), 50 * 50 * 4);
+    }
+
+    #[test]
+    fn test_set_pixels() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let mut pixels = png.pixels().unwrap();
+        pixels.samples_mut()[0] ^= 1;
+        png.set_pixels(&pixels).unwrap();
+
+        let reparsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+        assert_eq!(reparsed.pixels().unwrap(), pixels);
+        assert_eq!(
+            reparsed.chunks().last().unwrap().chunk_type().to_string(),
+            "IEND"
+        );
+    }
+
+    #[test]
+    fn test_split_trailing() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend(b"appended data after the end chunk");
+        let (png, trailing) = Png::split_trailing(&bytes).unwrap();
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+        assert_eq!(trailing, b"appended data after the end chunk");
+
+        let (_, trailing) = Png::split_trailing(&PNG_FILE).unwrap();
+        assert!(trailing.is_empty());
+    }
+
+    #[test]
+    fn test_malformed_input_is_an_error() {
+        for end in 0..PNG_FILE.len() {
+            if let Ok(png) = Png::try_from(&PNG_FILE[..end]) {
+                let _ = png.pixels();
+            }
+        }
+
+        let mut bytes = PNG_FILE.to_vec();
+        for index in 8..bytes.len() {
+            bytes[index] ^= 0xff;
+            if let Ok(png) = Png::try_from(bytes.as_slice()) {
+                let _ = png.pixels();
+            }
+            let _ = Png::split_trailing(&bytes);
+            bytes[index] ^= 0xff;
+        }
+    }
+
+    #[test]
+    fn test_overlay() {
+        let mut bytes = PNG_FILE.to_vec();
+        bytes.extend(b"short");
+        let mut png = Png::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(png.overlay(), b"short");
+        assert_eq!(png.as_bytes(), bytes);
+
+        png.set_overlay(vec![]);
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+
+        let mut no_iend = testing_png().as_bytes();
+        no_iend.extend(b"tail");
+        assert!(Png::try_from(no_iend.as_slice()).is_err());
+    }
+
     #[test]
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
@@ -0,0 +1,32 @@
+use crate::Error;
+use serde::Serialize;
+
+/// How far a long running command has got, reported as it works so a
+/// frontend can show more than a spinner.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+#[serde(tag = "stage", rename_all = "lowercase")]
+pub enum Progress {
+    /// Chunks read so far, and the bytes of the file they took up.
+    Parsing {
+        bytes_parsed: u64,
+        bytes_total: u64,
+        chunks_processed: usize,
+    },
+    /// Payload bits written into, or read back from, the pixels.
+    Embedding { bits_embedded: u64, bits_total: u64 },
+    Extracting { bits_extracted: u64, bits_total: u64 },
+    /// Channels run through the steganalysis attacks.
+    Analyzing {
+        channels_analyzed: usize,
+        channels_total: usize,
+    },
+}
+
+/// Bits between two [`Progress::Embedding`] or [`Progress::Extracting`]
+/// reports, so large payloads do not flood the observer.
+pub const BITS_PER_REPORT: u64 = 1 << 14;
+
+/// Receives progress reports; `&mut |_| Ok(())` ignores them. An error
+/// stops the command, which fails with it.
+pub type Observer<'a> = &'a mut dyn FnMut(Progress) -> Result<(), Error>;
@@ -0,0 +1,315 @@
+use crate::audit::{CRITICAL_CHUNKS, KNOWN_CHUNKS};
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::Error;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use std::ops::Range;
+
+/// Bytes [`resync`] may checksum over a whole repair, per byte of input.
+/// Without a bound a file packed with plausible headers claiming long
+/// chunks would make repair quadratic.
+const CRC_BUDGET_PER_BYTE: usize = 4;
+
+/// A chunk whose stored CRC does not match its contents.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct BadCrc {
+    pub offset: usize,
+    pub chunk_type: String,
+    pub stored: u32,
+    pub computed: u32,
+}
+
+/// Bytes that did not parse as a chunk and were skipped to resynchronize.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct SkippedBytes {
+    pub offset: usize,
+    pub length: usize,
+}
+
+/// An ancillary chunk recovered from the file, with its payload.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct SalvagedChunk {
+    pub offset: usize,
+    pub chunk_type: String,
+    pub crc_ok: bool,
+    /// The payload as text if it is printable UTF-8, otherwise as hex.
+    pub data: String,
+}
+
+/// Everything a repair pass found and recovered.
+#[derive(Debug, Default, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct RepairReport {
+    pub invalid_signature: bool,
+    pub chunks: usize,
+    pub bad_crcs: Vec<BadCrc>,
+    pub skipped: Vec<SkippedBytes>,
+    pub missing_iend: bool,
+    pub trailing_bytes: usize,
+    pub salvaged: Vec<SalvagedChunk>,
+}
+
+impl Display for RepairReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "recovered {} chunks", self.chunks)?;
+        if self.invalid_signature {
+            write!(f, "\ninvalid signature")?;
+        }
+        for bad in &self.bad_crcs {
+            write!(
+                f,
+                "\nbad CRC in {} at offset {}: stored {:#010x}, computed {:#010x}",
+                bad.chunk_type, bad.offset, bad.stored, bad.computed
+            )?;
+        }
+        for skipped in &self.skipped {
+            write!(
+                f,
+                "\nskipped {} bytes at offset {}",
+                skipped.length, skipped.offset
+            )?;
+        }
+        if self.missing_iend {
+            write!(f, "\nmissing IEND")?;
+        }
+        if self.trailing_bytes > 0 {
+            write!(f, "\n{} bytes after IEND", self.trailing_bytes)?;
+        }
+        for chunk in &self.salvaged {
+            let crc = if chunk.crc_ok { "" } else { " (bad CRC)" };
+            write!(f, "\nsalvaged {}{}: {}", chunk.chunk_type, crc, chunk.data)?;
+        }
+        Ok(())
+    }
+}
+
+/// Recovers as much of the image in `bytes` as possible.
+///
+/// Chunks with a bad CRC are recorded rather than rejected. They are kept
+/// with a recomputed CRC when `fix_crc` is set and dropped otherwise.
+/// Garbage or a truncated chunk is skipped by scanning ahead for the next
+/// plausible chunk header. A missing IEND is added back.
+pub fn repair(bytes: &[u8], fix_crc: bool) -> Result<(Png, RepairReport), Error> {
+    let mut report = RepairReport {
+        invalid_signature: !bytes.starts_with(&Png::STANDARD_HEADER),
+        ..Default::default()
+    };
+    let mut chunks = vec![];
+    let mut offset = Png::STANDARD_HEADER.len();
+    let mut seen_iend = false;
+    let mut budget = bytes.len().saturating_mul(CRC_BUDGET_PER_BYTE);
+
+    while offset < bytes.len() {
+        let parsed = chunk_at(bytes, offset).filter(|(chunk_type, data, stored)| {
+            // A header that overruns a later intact chunk was most likely
+            // truncated, so give up on it rather than swallow that chunk.
+            Chunk::checksum(&chunk_type.bytes(), data) == *stored
+                || resync(bytes, offset + 8..offset + data.len() + 12, true, &mut budget).is_none()
+        });
+        let Some((chunk_type, data, stored)) = parsed else {
+            match resync(bytes, offset + 1..bytes.len(), false, &mut budget) {
+                Some(next) => {
+                    report.skipped.push(SkippedBytes {
+                        offset,
+                        length: next - offset,
+                    });
+                    offset = next;
+                    continue;
+                }
+                None => {
+                    report.skipped.push(SkippedBytes {
+                        offset,
+                        length: bytes.len() - offset,
+                    });
+                    break;
+                }
+            }
+        };
+
+        let chunk = Chunk::new(chunk_type, data.to_vec());
+        let crc_ok = chunk.crc() == stored;
+        if !crc_ok {
+            report.bad_crcs.push(BadCrc {
+                offset,
+                chunk_type: chunk.chunk_type().to_string(),
+                stored,
+                computed: chunk.crc(),
+            });
+        }
+        let name = chunk.chunk_type().to_string();
+        if !CRITICAL_CHUNKS.contains(&name.as_str()) {
+            report.salvaged.push(SalvagedChunk {
+                offset,
+                chunk_type: name.clone(),
+                crc_ok,
+                data: printable(data),
+            });
+        }
+
+        offset += data.len() + 12;
+        if crc_ok || fix_crc {
+            chunks.push(chunk);
+        }
+        if name == "IEND" {
+            seen_iend = true;
+            report.trailing_bytes = bytes.len() - offset;
+            break;
+        }
+    }
+
+    if chunks.is_empty() {
+        return Err(Error::InvalidImage(
+            "no chunks could be recovered".to_string(),
+        ));
+    }
+    if !seen_iend {
+        report.missing_iend = true;
+        chunks.push(Chunk::new(ChunkType::try_from(*b"IEND")?, vec![]));
+    }
+    report.chunks = chunks.len();
+    Ok((Png::from_chunks(chunks), report))
+}
+
+fn printable(data: &[u8]) -> String {
+    match std::str::from_utf8(data) {
+        Ok(text) if !text.chars().any(|c| c.is_control() && !c.is_whitespace()) => {
+            text.to_string()
+        }
+        _ => data.iter().map(|b| format!("{:02x}", b)).collect(),
+    }
+}
+
+/// Parses the chunk at `offset` if its header is plausible and it fits in
+/// `bytes`, returning its type, data and stored CRC without checking it.
+fn chunk_at(bytes: &[u8], offset: usize) -> Option<(ChunkType, &[u8], u32)> {
+    let header = bytes.get(offset..offset.checked_add(8)?)?;
+    let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let chunk_type = ChunkType::try_from([header[4], header[5], header[6], header[7]]).ok()?;
+    let data_end = offset.checked_add(8)?.checked_add(length)?;
+    let data = bytes.get(offset + 8..data_end)?;
+    let crc = bytes.get(data_end..data_end.checked_add(4)?)?;
+    let stored = u32::from_be_bytes([crc[0], crc[1], crc[2], crc[3]]);
+    Some((chunk_type, data, stored))
+}
+
+/// Finds the first offset in `range` holding a chunk with a matching CRC,
+/// or unless `strict`, of a known type, so random letters in garbage are
+/// not mistaken for a chunk header. CRCs are only computed while `budget`
+/// bytes remain; after that only known types are found.
+fn resync(bytes: &[u8], range: Range<usize>, strict: bool, budget: &mut usize) -> Option<usize> {
+    range.into_iter().find(|&offset| {
+        chunk_at(bytes, offset).is_some_and(|(chunk_type, data, stored)| {
+            if !strict && KNOWN_CHUNKS.contains(&chunk_type.to_string().as_str()) {
+                return true;
+            }
+            if data.len() > *budget {
+                return false;
+            }
+            *budget -= data.len();
+            Chunk::checksum(&chunk_type.bytes(), data) == stored
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::Pixels;
+    use std::str::FromStr;
+
+    fn image() -> Vec<u8> {
+        let pixels = Pixels::new(4, 4, 8, 0, vec![9; 16]).unwrap();
+        let mut png = Png::from_pixels(&pixels);
+        let note = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hidden".to_vec());
+        png.insert_chunk(note, &crate::png::Position::End).unwrap();
+        png.as_bytes()
+    }
+
+    fn offset_of(bytes: &[u8], chunk_type: &[u8; 4]) -> usize {
+        bytes.windows(4).position(|w| w == chunk_type).unwrap() - 4
+    }
+
+    #[test]
+    fn test_clean_image() {
+        let bytes = image();
+        let (png, report) = repair(&bytes, false).unwrap();
+        assert_eq!(png.as_bytes(), bytes);
+        assert!(report.bad_crcs.is_empty() && report.skipped.is_empty());
+        assert_eq!(report.salvaged.len(), 1);
+        assert_eq!(report.salvaged[0].data, "hidden");
+    }
+
+    #[test]
+    fn test_bad_crc() {
+        let mut bytes = image();
+        let note = offset_of(&bytes, b"ruSt");
+        bytes[note + 8] = b'H';
+
+        let (png, report) = repair(&bytes, false).unwrap();
+        assert_eq!(report.bad_crcs.len(), 1);
+        assert_eq!(report.bad_crcs[0].chunk_type, "ruSt");
+        assert!(!report.salvaged[0].crc_ok);
+        assert_eq!(report.salvaged[0].data, "Hidden");
+        assert!(png.chunk_by_type("ruSt").is_none());
+
+        let (png, _) = repair(&bytes, true).unwrap();
+        assert_eq!(png.chunk_by_type("ruSt").unwrap().data(), b"Hidden");
+        assert!(Png::try_from(png.as_bytes().as_slice()).is_ok());
+    }
+
+    #[test]
+    fn test_resync_after_garbage_and_truncation() {
+        let bytes = image();
+        let note = offset_of(&bytes, b"ruSt");
+        let mut damaged = bytes[..note].to_vec();
+        damaged.extend(b"garbage!");
+        // The ruSt chunk loses its last three data bytes.
+        damaged.extend(&bytes[note..note + 8 + 3]);
+        damaged.extend(&bytes[note + 8 + 6 + 4..]);
+
+        let (png, report) = repair(&damaged, false).unwrap();
+        // The garbage and the truncated chunk are skipped as one run.
+        assert_eq!(report.skipped.len(), 1);
+        assert_eq!(report.skipped[0].offset, note);
+        assert_eq!(report.skipped[0].length, 8 + 8 + 3);
+        let types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_crafted_headers_do_not_make_repair_quadratic() {
+        // Every eighth byte starts a plausible header claiming most of the
+        // rest of the file, each of which would otherwise be checksummed.
+        let mut crafted = Png::STANDARD_HEADER.to_vec();
+        for _ in 0..(1 << 15) {
+            crafted.extend(100_000u32.to_be_bytes());
+            crafted.extend(b"ruSt");
+        }
+        crafted.extend(&image()[Png::STANDARD_HEADER.len()..]);
+
+        let (png, report) = repair(&crafted, false).unwrap();
+        assert!(!report.skipped.is_empty());
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+    }
+
+    #[test]
+    fn test_missing_iend_and_signature() {
+        let bytes = image();
+        let mut damaged = bytes[..offset_of(&bytes, b"IEND")].to_vec();
+        damaged[1] = b'X';
+        let (png, report) = repair(&damaged, false).unwrap();
+        assert!(report.invalid_signature && report.missing_iend);
+        assert_eq!(png.chunks().last().unwrap().chunk_type().to_string(), "IEND");
+
+        assert!(repair(b"not a png at all", true).is_err());
+    }
+}
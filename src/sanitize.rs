@@ -0,0 +1,164 @@
+use crate::audit::CRITICAL_CHUNKS;
+use crate::pixels::Pixels;
+use crate::png::Png;
+use crate::Error;
+use rand::Rng;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct RemovedChunk {
+    pub chunk_type: String,
+    pub size: usize,
+}
+
+/// Everything a sanitize pass stripped from an image.
+#[derive(Debug, Default, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct SanitizeReport {
+    pub removed_chunks: Vec<RemovedChunk>,
+    pub trailing_bytes: usize,
+    pub randomized_samples: usize,
+}
+
+impl Display for SanitizeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "removed {} chunks", self.removed_chunks.len())?;
+        for chunk in &self.removed_chunks {
+            write!(f, "\n  {} ({} bytes)", chunk.chunk_type, chunk.size)?;
+        }
+        write!(f, "\nremoved {} bytes after IEND", self.trailing_bytes)?;
+        write!(f, "\nrandomized {} sample LSBs", self.randomized_samples)
+    }
+}
+
+/// Rebuilds the image in `bytes` with only the standard critical chunks
+/// and the ancillary chunk types listed in `allow`, dropping anything
+/// after IEND. The image data is decoded and compressed afresh, so bytes
+/// after the end of the zlib stream do not survive either. With
+/// `randomize_lsb` every non-alpha sample gets a fresh random least
+/// significant bit, destroying any pixel-domain payload. Images whose
+/// pixels would take up more than `limit` bytes are refused.
+pub fn sanitize(
+    bytes: &[u8],
+    allow: &[String],
+    randomize_lsb: bool,
+    limit: usize,
+) -> Result<(Png, SanitizeReport), Error> {
+    let (png, trailing) = Png::split_trailing(bytes)?;
+    let mut report = SanitizeReport {
+        trailing_bytes: trailing.len(),
+        ..Default::default()
+    };
+
+    let (kept, removed): (Vec<_>, Vec<_>) = png.into_chunks().into_iter().partition(|c| {
+        let name = c.chunk_type().to_string();
+        CRITICAL_CHUNKS.contains(&name.as_str()) || allow.contains(&name)
+    });
+    report.removed_chunks = removed
+        .iter()
+        .map(|c| RemovedChunk {
+            chunk_type: c.chunk_type().to_string(),
+            size: c.data().len(),
+        })
+        .collect();
+    let mut png = Png::from_chunks(kept);
+
+    let mut pixels = Pixels::decode(&png, limit)?;
+    if randomize_lsb {
+        if pixels.is_palette() {
+            return Err(Error::Unsupported(
+                "palette indices cannot be randomized".to_string(),
+            ));
+        }
+        let mut rng = rand::thread_rng();
+        for index in 0..pixels.samples().len() {
+            if !pixels.is_alpha_sample(index) {
+                let sample = &mut pixels.samples_mut()[index];
+                *sample = (*sample & !1) | rng.gen_range(0..=1);
+                report.randomized_samples += 1;
+            }
+        }
+    }
+    png.set_pixels(&pixels)?;
+
+    Ok((png, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::pixels::DEFAULT_DECODE_LIMIT;
+    use std::str::FromStr;
+
+    fn dirty_image() -> Vec<u8> {
+        let pixels = Pixels::new(8, 8, 8, 2, vec![100; 8 * 8 * 3]).unwrap();
+        let mut chunks = Png::from_pixels(&pixels).into_chunks();
+        let iend = chunks.pop().unwrap();
+        for (chunk_type, data) in [("gAMA", &b"\0\0\xb1\x8f"[..]), ("ruSt", b"secret")] {
+            chunks.push(Chunk::new(
+                ChunkType::from_str(chunk_type).unwrap(),
+                data.to_vec(),
+            ));
+        }
+        chunks.push(iend);
+        let mut bytes = Png::from_chunks(chunks).as_bytes();
+        bytes.extend(b"trailing");
+        bytes
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_strips_ancillary_and_trailing() {
+        let (png, report) = sanitize(&dirty_image(), &[], false, DEFAULT_DECODE_LIMIT).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "IDAT", "IEND"]);
+        assert_eq!(report.removed_chunks.len(), 2);
+        assert_eq!(report.removed_chunks[1].chunk_type, "ruSt");
+        assert_eq!(report.removed_chunks[1].size, 6);
+        assert_eq!(report.trailing_bytes, 8);
+        assert_eq!(report.randomized_samples, 0);
+    }
+
+    #[test]
+    fn test_allow_list() {
+        let (png, report) = sanitize(&dirty_image(), &["gAMA".to_string()], false, DEFAULT_DECODE_LIMIT).unwrap();
+        assert_eq!(chunk_types(&png), vec!["IHDR", "IDAT", "gAMA", "IEND"]);
+        assert_eq!(report.removed_chunks.len(), 1);
+    }
+
+    #[test]
+    fn test_image_data_is_reencoded() {
+        let pixels = Pixels::new(8, 8, 8, 2, vec![100; 8 * 8 * 3]).unwrap();
+        let mut chunks = Png::from_pixels(&pixels).into_chunks();
+        // Bytes after the end of the zlib stream, and an IDAT of their own.
+        let mut idat = chunks[1].data().to_vec();
+        idat.extend(b"appended");
+        chunks[1] = Chunk::new(ChunkType::from_str("IDAT").unwrap(), idat);
+        chunks.insert(2, Chunk::new(ChunkType::from_str("IDAT").unwrap(), b"extra".to_vec()));
+        let dirty = Png::from_chunks(chunks).as_bytes();
+
+        let (png, _) = sanitize(&dirty, &[], false, DEFAULT_DECODE_LIMIT).unwrap();
+        let bytes = png.as_bytes();
+        assert!(!bytes.windows(8).any(|w| w == b"appended"));
+        assert!(!bytes.windows(5).any(|w| w == b"extra"));
+        assert_eq!(png.pixels().unwrap(), pixels);
+    }
+
+    #[test]
+    fn test_randomize_lsb() {
+        let (png, report) = sanitize(&dirty_image(), &[], true, DEFAULT_DECODE_LIMIT).unwrap();
+        assert_eq!(report.randomized_samples, 8 * 8 * 3);
+        let pixels = png.pixels().unwrap();
+        assert!(pixels.samples().iter().all(|&s| s == 100 || s == 101));
+        assert!(pixels.samples().contains(&101));
+    }
+}
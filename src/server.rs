@@ -0,0 +1,2366 @@
+use std::path::PathBuf;
+use pngme::png::{ChunkInfo, Occurrence, Position};
+use pngme::storage::{self, Expiry, Storage, StoredFile};
+use crate::auth::{self, Cors, ReadAccess, Tokens, WriteAccess};
+use crate::jobs::{self, JobView, Jobs, Task};
+use crate::openapi::{self, Api, Operation};
+use pngme::commands::Output;
+use pngme::pixels::DEFAULT_DECODE_LIMIT;
+use pngme::progress::Progress;
+use pngme::{analysis, audit, commands, lsb, repair, sanitize, Error};
+use std::str::FromStr;
+use rocket::data::{ByteUnit, ToByteUnit};
+use rocket::figment::Figment;
+use rocket::fairing::AdHoc;
+use rocket::http::Header;
+use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::fs::File;
+use std::fs;
+use rocket::{catch, catchers, data, delete, error, get, info, options, post, routes, Build, Request, Rocket, Shutdown, State};
+use rocket::serde::{json::Json, Deserialize , Serialize};
+use rocket::response::content::RawJson;
+use rocket::response::status::Custom;
+use rocket::response::stream::{Event, EventStream};
+use rocket::http::{ContentType, Status};
+use rocket_multipart_form_data::{MultipartFormData, MultipartFormDataField, MultipartFormDataOptions};
+use schemars::JsonSchema;
+
+mod v1;
+
+
+
+#[derive(Serialize, JsonSchema)]
+struct StatusResponse {
+    encoding: &'static str,
+    decoding: &'static str,
+    metadata: &'static str,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct EncodeData {
+    id: String,
+    chunk_type: String,
+    message: String,
+    #[serde(default)]
+    position: Option<String>,
+    #[serde(default)]
+    force: bool,
+    #[serde(flatten)]
+    output: OutputData,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct DecodeData {
+    id: String,
+    chunk_type: String,
+    #[serde(default)]
+    occurrence: Option<String>,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct EmbedData {
+    id: String,
+    message: String,
+    key: String,
+    #[serde(default)]
+    method: lsb::PixelMethod,
+    #[serde(flatten)]
+    output: OutputData,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct ExtractData {
+    id: String,
+    key: String,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct SanitizeData {
+    id: String,
+    #[serde(default)]
+    keep: Vec<String>,
+    #[serde(default)]
+    randomize_lsb: bool,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct PrintData {
+    id: String,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct RemoveData {
+    id: String,
+    chunk_type: String,
+    #[serde(default)]
+    occurrence: Option<String>,
+    #[serde(flatten)]
+    output: OutputData,
+}
+
+/// Edits are stored under a new ID unless `in_place` is set, in which case
+/// `backup` first copies the original to a new ID.
+#[derive(Deserialize, Debug, Default, JsonSchema)]
+struct OutputData {
+    #[serde(default)]
+    in_place: bool,
+    #[serde(default)]
+    backup: bool,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct RepairData {
+    id: String,
+    /// Store the repaired image under a new ID
+    #[serde(default)]
+    save: bool,
+    #[serde(default)]
+    fix_crc: bool,
+}
+
+/// `in_place` and `backup` apply to writing and removing the overlay.
+#[derive(Deserialize, Debug, JsonSchema)]
+struct OverlayData {
+    id: String,
+    #[serde(default)]
+    message: String,
+    #[serde(flatten)]
+    output: OutputData,
+}
+
+#[derive(Deserialize, Debug, JsonSchema)]
+struct ListData {
+    id: String,
+    chunk_type: String,
+}
+
+#[derive(rocket::serde::Serialize, JsonSchema)]
+struct PrintResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    chunks: Option<Vec<String>>,
+    message: String
+}
+
+#[derive(Serialize, JsonSchema)]
+struct EditResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    chunks: Option<Vec<String>>,
+    /// ID holding the edited image
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    backup: Option<String>,
+    message: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ImagesResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    images: Option<Vec<StoredFile>>,
+    /// Bytes stored in the caller's workspace
+    usage: Option<u64>,
+    quota: Option<u64>,
+    message: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct DecodeResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    chunks: Option<Vec<String>>,
+    messages: Option<Vec<String>>,
+    message: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ListResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    chunks: Option<Vec<ChunkInfo>>,
+    message: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct AnalyzeResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    report: Option<analysis::AnalysisReport>,
+    message: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct AuditResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    report: Option<audit::AuditReport>,
+    message: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct SanitizeResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    report: Option<sanitize::SanitizeReport>,
+    id: Option<String>,
+    message: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct RepairResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    report: Option<repair::RepairReport>,
+    id: Option<String>,
+    message: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct UploadResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    id: Option<String>,
+    message: String,
+}
+
+// HTTP status for each library error, so clients need not parse messages
+fn status_for(error: &Error) -> Status {
+    match error {
+        Error::FileNotFound(_) | Error::ChunkNotFound(_) => Status::NotFound,
+        Error::InvalidChunkType(_) | Error::InvalidArgument(_) => Status::BadRequest,
+        Error::InvalidSignature
+        | Error::CrcMismatch { .. }
+        | Error::TruncatedChunk { .. }
+        | Error::InvalidImage(_)
+        | Error::Unsupported(_) => Status::UnprocessableEntity,
+        Error::PayloadTooLarge { .. } => Status::PayloadTooLarge,
+        Error::QuotaExceeded { .. } => Status::InsufficientStorage,
+        Error::AuthFailure(_) => Status::Unauthorized,
+        Error::Cancelled => Status::Conflict,
+        Error::Io(_) => Status::InternalServerError,
+    }
+}
+
+// Path of the stored image `id`; requests never name server paths directly
+fn resolve(storage: &Storage, id: &str) -> Result<String, Error> {
+    Ok(storage.path(id)?.to_string_lossy().into_owned())
+}
+
+// Runs `edit` on the image `id`, writing to a fresh ID unless the request
+// asks for an in-place edit. Returns the ID holding the result and the
+// backup ID, if any. On failure, or when the result does not fit the quota,
+// reserved IDs are released again and an image edited in place is restored.
+fn edit_stored<T>(
+    storage: &Storage,
+    id: &str,
+    output: &OutputData,
+    edit: impl FnOnce(&str, &Output) -> Result<T, Error>,
+) -> Result<(T, String, Option<String>), Error> {
+    let path = resolve(storage, id)?;
+    let (output_id, target, original, backup) = if output.in_place {
+        // Not an ID, so it neither counts against the quota nor gets listed.
+        let original = tempfile::NamedTempFile::new_in(storage.root())?;
+        fs::copy(&path, original.path())?;
+        let backup = if output.backup {
+            let (backup_id, backup_path) = storage.reserve()?;
+            fs::copy(&path, &backup_path)
+                .inspect_err(|_| { let _ = fs::remove_file(&backup_path); })?;
+            storage.admit(&backup_id)?;
+            Some((backup_id, backup_path))
+        } else {
+            None
+        };
+        (id.to_string(), Output::InPlace { backup: false }, Some(original), backup)
+    } else {
+        let (output_id, output_path) = storage.reserve()?;
+        (output_id, Output::File(output_path.to_string_lossy().into_owned()), None, None)
+    };
+
+    let result = edit(&path, &target).and_then(|result| {
+        if output.in_place {
+            storage.check_usage()?;
+        } else {
+            storage.admit(&output_id)?;
+        }
+        Ok(result)
+    });
+    match result {
+        Ok(result) => Ok((result, output_id, backup.map(|(backup_id, _)| backup_id))),
+        Err(e) => {
+            if let Output::File(output_path) = &target {
+                let _ = fs::remove_file(output_path);
+            }
+            if let Some(original) = original {
+                let _ = original.persist(&path);
+            }
+            if let Some((_, backup_path)) = backup {
+                let _ = fs::remove_file(backup_path);
+            }
+            Err(e)
+        }
+    }
+}
+
+// Root endpoint: /
+#[get("/")]
+fn index() -> &'static str {
+    "Hello, Rocket!"
+}
+
+// Encode endpoint: /encode
+#[post("/encode", data = "<encode_data>")]
+async fn encode_endpoint(access: WriteAccess, encode_data: Json<EncodeData>) -> Custom<Json<EditResponse>> {
+    let storage = access.workspace;
+    let encode_data = encode_data.into_inner();
+    let id = &encode_data.id;
+    let chunk_type = encode_data.chunk_type.clone();
+    let message = &encode_data.message;
+
+    info!("Encoding {} bytes into {} as {}", message.len(), id, chunk_type);
+
+    let encoded = blocking(move || {
+        let position = encode_data
+            .position
+            .as_deref()
+            .map_or(Ok(Position::Auto), Position::from_str)?;
+        edit_stored(&storage, &encode_data.id, &encode_data.output, |path, output| {
+            commands::encode(path, &encode_data.chunk_type, &encode_data.message, &position, encode_data.force, output)
+        })
+    });
+    match encoded.await {
+        Ok((_, output_id, backup)) => {
+            let response = EditResponse {
+                status: "success".to_string(),
+                code: None,
+                chunks: Some(vec![chunk_type]),
+                message: format!("Encoding successful! Stored as {}", output_id),
+                id: Some(output_id),
+                backup,
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error encoding: {}", e);
+            let response = EditResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                chunks: None,
+                id: None,
+                backup: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Decode endpoint: /decode
+#[post("/decode", data = "<decode_data>")]
+fn decode_endpoint(access: ReadAccess, decode_data: Json<DecodeData>) -> Custom<Json<DecodeResponse>> {
+    let storage = &access.workspace;
+    let id = &decode_data.id;
+    let chunk_type = &decode_data.chunk_type;
+
+    info!("Decoding {} from {}", chunk_type, id);
+
+    let occurrence = parse_occurrence(decode_data.occurrence.as_deref());
+    match occurrence.and_then(|o| commands::decode(&resolve(storage, id)?, chunk_type, &o)) {
+        Ok(messages) => {
+            let response = DecodeResponse {
+                status: "success".to_string(),
+                code: None,
+                chunks: Some(vec![chunk_type.clone()]),
+                message: messages.join("\n"),
+                messages: Some(messages),
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error decoding: {}", e);
+            let response = DecodeResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                chunks: None,
+                messages: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Embed endpoint: /embed
+#[post("/embed", data = "<embed_data>")]
+async fn embed_endpoint(access: WriteAccess, limit: &State<DecodeLimit>, embed_data: Json<EmbedData>) -> Custom<Json<EditResponse>> {
+    let (storage, limit) = (access.workspace, limit.0);
+    let embed_data = embed_data.into_inner();
+    let id = &embed_data.id;
+    let method = embed_data.method;
+
+    info!("Embedding into {} with {:?}", id, method);
+
+    let embedded = blocking(move || {
+        edit_stored(&storage, &embed_data.id, &embed_data.output, |path, output| {
+            commands::embed(path, &embed_data.message, &embed_data.key, method, limit, output)
+        })
+    });
+    match embedded.await {
+        Ok((_, output_id, backup)) => {
+            let response = EditResponse {
+                status: "success".to_string(),
+                code: None,
+                chunks: None,
+                message: format!("Embedding successful! Stored as {}", output_id),
+                id: Some(output_id),
+                backup,
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error embedding: {}", e);
+            let response = EditResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                chunks: None,
+                id: None,
+                backup: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Extract endpoint: /extract
+#[post("/extract", data = "<extract_data>")]
+async fn extract_endpoint(access: ReadAccess, limit: &State<DecodeLimit>, extract_data: Json<ExtractData>) -> Custom<Json<PrintResponse>> {
+    let (storage, limit) = (access.workspace, limit.0);
+    let ExtractData { id, key } = extract_data.into_inner();
+
+    info!("Extracting from {}", id);
+
+    match blocking(move || commands::extract(&resolve(&storage, &id)?, &key, limit)).await {
+        Ok(message) => {
+            let response = PrintResponse {
+                status: "success".to_string(),
+                code: None,
+                chunks: None,
+                message,
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error extracting: {}", e);
+            let response = PrintResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                chunks: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Print endpoint: /print
+#[post("/print", data = "<print_data>")]
+fn print_endpoint(access: ReadAccess, print_data: Json<PrintData>) -> Custom<Json<PrintResponse>> {
+    let storage = &access.workspace;
+    let id = &print_data.id;
+    info!("Printing {}", id);
+
+    // Call the print function logic and handle the result
+    match resolve(storage, id).and_then(|path| commands::print(&path)) {
+        Ok(listing) => {
+            let mut message = format!("found {} chunks", listing.chunk_types.len());
+            if listing.overlay_bytes > 0 {
+                message.push_str(&format!(" and {} bytes after IEND", listing.overlay_bytes));
+            }
+            let response = PrintResponse {
+                status: "success".to_string(),
+                code: None,
+                chunks: Some(listing.chunk_types),
+                message,
+            };
+            Custom(Status::Ok, Json(response)) // Return the chunks as JSON
+        },
+        Err(e) => {
+            error!("Error printing: {}", e);
+            let response = PrintResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                chunks: None,
+                message: e.to_string()
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+
+
+// Audit endpoint: /audit
+#[post("/audit", data = "<audit_data>")]
+fn audit_endpoint(access: ReadAccess, audit_data: Json<PrintData>) -> Custom<Json<AuditResponse>> {
+    let storage = &access.workspace;
+    let id = &audit_data.id;
+    info!("Auditing {}", id);
+
+    match resolve(storage, id).and_then(|path| commands::audit(&path)) {
+        Ok(report) => {
+            let response = AuditResponse {
+                status: "success".to_string(),
+                code: None,
+                message: format!("found {} suspicious items", report.findings.len()),
+                report: Some(report),
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error auditing: {}", e);
+            let response = AuditResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                report: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Analyze endpoint: /analyze
+#[post("/analyze", data = "<analyze_data>")]
+async fn analyze_endpoint(access: ReadAccess, limit: &State<DecodeLimit>, analyze_data: Json<PrintData>) -> Custom<Json<AnalyzeResponse>> {
+    let (storage, limit) = (access.workspace, limit.0);
+    let id = analyze_data.into_inner().id;
+    info!("Analyzing {}", id);
+
+    match blocking(move || commands::analyze(&resolve(&storage, &id)?, limit)).await {
+        Ok(report) => {
+            let response = AnalyzeResponse {
+                status: "success".to_string(),
+                code: None,
+                message: format!("verdict: {:?}", report.verdict),
+                report: Some(report),
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error analyzing: {}", e);
+            let response = AnalyzeResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                report: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Bit plane endpoint: /bitplane
+#[get("/bitplane?<id>&<channel>&<bit>&<diff>")]
+async fn bitplane_endpoint(
+    access: ReadAccess,
+    limit: &State<DecodeLimit>,
+    id: String,
+    channel: Option<usize>,
+    bit: Option<u8>,
+    diff: Option<String>,
+) -> std::result::Result<(ContentType, Vec<u8>), Custom<Json<PrintResponse>>> {
+    let (storage, limit) = (access.workspace, limit.0);
+    info!("Rendering bit plane of {}", id);
+
+    let rendered = blocking(move || {
+        let path = resolve(&storage, &id)?;
+        let diff = diff.map(|diff| resolve(&storage, &diff)).transpose()?;
+        commands::render_bitplane(&path, channel.unwrap_or(0), bit.unwrap_or(0), diff.as_deref(), limit)
+    });
+    rendered
+        .await
+        .map(|bytes| (ContentType::PNG, bytes))
+        .map_err(|e| {
+            error!("Error rendering bit plane: {}", e);
+            let response = PrintResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                chunks: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        })
+}
+
+// Remove endpoint: /remove
+#[post("/remove", data = "<remove_data>")]
+async fn remove_endpoint(access: WriteAccess, remove_data: Json<RemoveData>) -> Custom<Json<EditResponse>> {
+    let storage = access.workspace;
+    let remove_data = remove_data.into_inner();
+    let id = &remove_data.id;
+    let chunk_type = remove_data.chunk_type.clone();
+
+    info!("Removing {} from {}", chunk_type, id);
+
+    let removed = blocking(move || {
+        let occurrence = parse_occurrence(remove_data.occurrence.as_deref())?;
+        edit_stored(&storage, &remove_data.id, &remove_data.output, |path, output| {
+            commands::remove(path, &remove_data.chunk_type, &occurrence, output)
+        })
+    });
+    match removed.await {
+        Ok((count, output_id, backup)) => {
+            let response = EditResponse {
+                status: "success".to_string(),
+                code: None,
+                chunks: Some(vec![chunk_type.clone()]),
+                message: format!("Chunk {} removal successful! ({} removed, stored as {})", chunk_type, count, output_id),
+                id: Some(output_id),
+                backup,
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error removing chunk: {}", e);
+            let response = EditResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                chunks: None,
+                id: None,
+                backup: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// List endpoint: /list
+#[post("/list", data = "<list_data>")]
+fn list_endpoint(access: ReadAccess, list_data: Json<ListData>) -> Custom<Json<ListResponse>> {
+    let storage = &access.workspace;
+    let id = &list_data.id;
+    let chunk_type = &list_data.chunk_type;
+
+    match resolve(storage, id).and_then(|path| commands::list(&path, chunk_type)) {
+        Ok(chunks) => {
+            let response = ListResponse {
+                status: "success".to_string(),
+                code: None,
+                message: format!("{} {} chunks found", chunks.len(), chunk_type),
+                chunks: Some(chunks),
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error listing chunks: {}", e);
+            let response = ListResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                chunks: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Images endpoint: /images
+// Lists the images in the caller's workspace.
+#[get("/images")]
+fn images_endpoint(access: ReadAccess) -> Custom<Json<ImagesResponse>> {
+    let storage = &access.workspace;
+    match storage.list() {
+        Ok(images) => {
+            let usage = images.iter().map(|image| image.size).sum();
+            let response = ImagesResponse {
+                status: "success".to_string(),
+                code: None,
+                message: format!("{} images stored", images.len()),
+                images: Some(images),
+                usage: Some(usage),
+                quota: storage.quota(),
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error listing images: {}", e);
+            let response = ImagesResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                images: None,
+                usage: None,
+                quota: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Runs the image work of a handler on the blocking thread pool, so parsing
+// or rewriting a large image does not hold up the workers serving other
+// requests.
+async fn blocking<T: Send + 'static>(work: impl FnOnce() -> Result<T, Error> + Send + 'static) -> Result<T, Error> {
+    let result = tokio::task::spawn_blocking(work).await;
+    result.map_err(|e| Error::Io(std::io::Error::other(e)))?
+}
+
+fn parse_occurrence(occurrence: Option<&str>) -> Result<Occurrence, Error> {
+    occurrence.map_or(Ok(Occurrence::First), Occurrence::from_str)
+}
+
+// Sanitize endpoint: /sanitize
+#[post("/sanitize", data = "<sanitize_data>")]
+async fn sanitize_endpoint(access: WriteAccess, limit: &State<DecodeLimit>, sanitize_data: Json<SanitizeData>) -> Custom<Json<SanitizeResponse>> {
+    let (storage, limit) = (access.workspace, limit.0);
+    let sanitize_data = sanitize_data.into_inner();
+
+    info!("Sanitizing {}", sanitize_data.id);
+
+    match blocking(move || sanitize_stored(&storage, &sanitize_data, limit)).await {
+        Ok((output_id, report)) => {
+            let response = SanitizeResponse {
+                status: "success".to_string(),
+                code: None,
+                message: format!("Sanitized image stored as {}", output_id),
+                report: Some(report),
+                id: Some(output_id),
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error sanitizing: {}", e);
+            let response = SanitizeResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                report: None,
+                id: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Sanitizes the image into a new ID, returning the ID and the report
+fn sanitize_stored(storage: &Storage, sanitize_data: &SanitizeData, limit: usize) -> Result<(String, sanitize::SanitizeReport), Error> {
+    let path = resolve(storage, &sanitize_data.id)?;
+    let (output_id, output) = storage.reserve()?;
+    let target = Output::File(output.to_string_lossy().into_owned());
+    let report = commands::sanitize(&path, &target, &sanitize_data.keep, sanitize_data.randomize_lsb, limit)
+        .inspect_err(|_| { let _ = fs::remove_file(&output); })?;
+    storage.admit(&output_id)?;
+    Ok((output_id, report))
+}
+
+// Overlay endpoints: /overlay/read, /overlay/write, /overlay/remove
+#[post("/overlay/read", data = "<overlay_data>")]
+fn overlay_read_endpoint(access: ReadAccess, overlay_data: Json<OverlayData>) -> Custom<Json<PrintResponse>> {
+    let storage = &access.workspace;
+    match resolve(storage, &overlay_data.id).and_then(|path| commands::read_overlay(&path)) {
+        Ok(overlay) => {
+            let response = PrintResponse {
+                status: "success".to_string(),
+                code: None,
+                chunks: None,
+                message: String::from_utf8_lossy(&overlay).to_string(),
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => overlay_error(e),
+    }
+}
+
+#[post("/overlay/write", data = "<overlay_data>")]
+fn overlay_write_endpoint(access: WriteAccess, overlay_data: Json<OverlayData>) -> Custom<Json<EditResponse>> {
+    let storage = &access.workspace;
+    let written = edit_stored(storage, &overlay_data.id, &overlay_data.output, |path, output| {
+        commands::write_overlay(path, overlay_data.message.as_bytes(), output)
+    });
+    match written {
+        Ok((_, output_id, backup)) => {
+            let response = EditResponse {
+                status: "success".to_string(),
+                code: None,
+                chunks: None,
+                message: format!("Overlay written! Stored as {}", output_id),
+                id: Some(output_id),
+                backup,
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => overlay_edit_error(e),
+    }
+}
+
+#[post("/overlay/remove", data = "<overlay_data>")]
+fn overlay_remove_endpoint(access: WriteAccess, overlay_data: Json<OverlayData>) -> Custom<Json<EditResponse>> {
+    let storage = &access.workspace;
+    let removed = edit_stored(storage, &overlay_data.id, &overlay_data.output, |path, output| {
+        commands::write_overlay(path, &[], output)
+    });
+    match removed {
+        Ok((removed, output_id, backup)) => {
+            let response = EditResponse {
+                status: "success".to_string(),
+                code: None,
+                chunks: None,
+                message: format!("Removed {} bytes after IEND, stored as {}", removed, output_id),
+                id: Some(output_id),
+                backup,
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => overlay_edit_error(e),
+    }
+}
+
+fn overlay_edit_error(e: Error) -> Custom<Json<EditResponse>> {
+    error!("Error handling overlay: {}", e);
+    let response = EditResponse {
+        status: "error".to_string(),
+        code: Some(e.code()),
+        chunks: None,
+        id: None,
+        backup: None,
+        message: e.to_string(),
+    };
+    Custom(status_for(&e), Json(response))
+}
+
+fn overlay_error(e: Error) -> Custom<Json<PrintResponse>> {
+    error!("Error handling overlay: {}", e);
+    let response = PrintResponse {
+        status: "error".to_string(),
+        code: Some(e.code()),
+        chunks: None,
+        message: e.to_string(),
+    };
+    Custom(status_for(&e), Json(response))
+}
+
+// Repair endpoint: /repair
+#[post("/repair", data = "<repair_data>")]
+async fn repair_endpoint(access: WriteAccess, repair_data: Json<RepairData>) -> Custom<Json<RepairResponse>> {
+    let storage = access.workspace;
+    let repair_data = repair_data.into_inner();
+
+    info!("Repairing {}", repair_data.id);
+
+    let repaired = blocking(move || {
+        let path = resolve(&storage, &repair_data.id)?;
+        if !repair_data.save {
+            return Ok((None, commands::repair(&path, None, repair_data.fix_crc)?));
+        }
+        let (output_id, output) = storage.reserve()?;
+        let report = commands::repair(&path, Some(&Output::File(output.to_string_lossy().into_owned())), repair_data.fix_crc)
+            .inspect_err(|_| { let _ = fs::remove_file(&output); })?;
+        storage.admit(&output_id)?;
+        Ok((Some(output_id), report))
+    });
+    match repaired.await {
+        Ok((output_id, report)) => {
+            let message = match &output_id {
+                Some(output_id) => format!("Repaired image stored as {}", output_id),
+                None => "Repair report generated".to_string(),
+            };
+            let response = RepairResponse {
+                status: "success".to_string(),
+                code: None,
+                report: Some(report),
+                id: output_id,
+                message,
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error repairing: {}", e);
+            let response = RepairResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                report: None,
+                id: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Expiry requested by the optional `ttl` (seconds) and `once` upload
+// fields. A TTL never keeps an image past the workspace's retention.
+fn upload_expiry(storage: &Storage, form: &mut MultipartFormData) -> Result<Expiry, Error> {
+    let mut text = |name: &str| form.texts.remove(name).map(|mut fields| fields.remove(0).text);
+    let ttl = match text("ttl") {
+        Some(ttl) => Some(ttl.trim().parse::<u64>().map_err(|_| {
+            Error::InvalidArgument(format!("invalid ttl {:?}, expected seconds", ttl))
+        })?),
+        None => None,
+    };
+    let ttl = match (ttl, storage.retention()) {
+        (Some(ttl), Some(retention)) => Some(ttl.min(retention.as_secs())),
+        (ttl, _) => ttl,
+    };
+    Ok(Expiry {
+        expires_at: ttl.map(|ttl| storage::now().saturating_add(ttl)),
+        once: text("once").is_some_and(|once| once == "true"),
+    })
+}
+
+// Stores the `image` field of a multipart upload under a fresh ID. Fails
+// with the status, code and message to answer with.
+async fn store_upload(
+    storage: &Storage,
+    content_type: &ContentType,
+    data: data::Data<'_>,
+) -> Result<String, (Status, &'static str, String)> {
+    if !content_type.is_form_data() {
+        let message = "Not a valid format , ensure it's form data format".to_string();
+        return Err((Status::BadRequest, "invalid_form", message));
+    }
+
+    // Kept in memory rather than a temp file, which the form parser does not
+    // flush before handing it over.
+    let options = MultipartFormDataOptions::with_multipart_form_data_fields(vec![
+        MultipartFormDataField::raw("image")
+            .size_limit(u64::from(32.mebibytes())),
+        MultipartFormDataField::text("ttl"),
+        MultipartFormDataField::text("once"),
+    ]);
+
+    let mut multi_form_data = MultipartFormData::parse(content_type, data, options)
+        .await
+        .map_err(|e| (Status::BadRequest, "invalid_form", format!("Failed to parse form data: {}", e)))?;
+
+    let expiry = upload_expiry(storage, &mut multi_form_data);
+    let Some(mut file_fields) = multi_form_data.raw.remove("image") else {
+        return Err((Status::BadRequest, "invalid_form", "Upload Failed".to_string()));
+    };
+    let file_field = file_fields.remove(0);
+
+    // The client's file name is never used on disk; the image is stored
+    // under a fresh ID instead.
+    let stored = expiry.and_then(|expiry| {
+        let id = storage.create(&file_field.raw)?;
+        if expiry != Expiry::default() {
+            storage
+                .set_expiry(&id, expiry)
+                .inspect_err(|_| { let _ = storage.remove(&id); })?;
+        }
+        Ok(id)
+    });
+    stored.map_err(|e| (status_for(&e), e.code(), format!("Failed to store file: {}", e)))
+}
+
+#[post("/upload", data = "<data>")]
+async fn upload(access: WriteAccess, content_type: &ContentType , data: data::Data<'_>) -> Custom<Json<UploadResponse>> {
+    match store_upload(&access.workspace, content_type, data).await {
+        Ok(id) => {
+            // Return success response with the image ID
+            let response = UploadResponse {
+                status: "success".to_string(),
+                code: None,
+                id: Some(id),
+                message: "File processed successfully.".to_string(),
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err((status, code, message)) => {
+            let response = UploadResponse {
+                status: "error".to_string(),
+                code: Some(code),
+                id: None,
+                message,
+            };
+            Custom(status, Json(response))
+        }
+    }
+}
+
+
+// Parses an in-memory multipart request. `image` and `message` are taken as
+// raw bytes, everything else in `fields` as text.
+async fn parse_inline_form(
+    content_type: &ContentType,
+    data: data::Data<'_>,
+    fields: &[&str],
+) -> Result<MultipartFormData, Custom<Json<PrintResponse>>> {
+    let invalid_form = |message: String| {
+        let response = PrintResponse {
+            status: "error".to_string(),
+            code: Some("invalid_form"),
+            chunks: None,
+            message,
+        };
+        Custom(Status::BadRequest, Json(response))
+    };
+    if !content_type.is_form_data() {
+        return Err(invalid_form("Not a valid format , ensure it's form data format".to_string()));
+    }
+
+    let mut form_fields = vec![
+        MultipartFormDataField::raw("image").size_limit(u64::from(32.mebibytes())),
+        MultipartFormDataField::raw("message").size_limit(u64::from(8.mebibytes())),
+    ];
+    form_fields.extend(fields.iter().map(MultipartFormDataField::text));
+    let options = MultipartFormDataOptions::with_multipart_form_data_fields(form_fields);
+
+    let form = MultipartFormData::parse(content_type, data, options)
+        .await
+        .map_err(|e| invalid_form(format!("Failed to parse form data: {}", e)))?;
+    if !form.raw.contains_key("image") {
+        return Err(invalid_form("Missing image field".to_string()));
+    }
+    Ok(form)
+}
+
+fn inline_raw(form: &mut MultipartFormData, name: &str) -> Option<Vec<u8>> {
+    form.raw.remove(name).map(|mut fields| fields.remove(0).raw)
+}
+
+fn inline_text(form: &mut MultipartFormData, name: &str) -> Option<String> {
+    form.texts.remove(name).map(|mut fields| fields.remove(0).text)
+}
+
+fn inline_error(e: Error) -> Custom<Json<PrintResponse>> {
+    error!("Error: {}", e);
+    let response = PrintResponse {
+        status: "error".to_string(),
+        code: Some(e.code()),
+        chunks: None,
+        message: e.to_string(),
+    };
+    Custom(status_for(&e), Json(response))
+}
+
+// Inline encode endpoint: /encode/inline
+// Takes the image and message in one multipart request and returns the
+// encoded PNG; nothing is written to storage.
+#[post("/encode/inline", data = "<data>")]
+async fn encode_inline_endpoint(
+    _access: WriteAccess,
+    content_type: &ContentType,
+    data: data::Data<'_>,
+) -> Result<(ContentType, Vec<u8>), Custom<Json<PrintResponse>>> {
+    let mut form = parse_inline_form(content_type, data, &["chunk_type", "position", "force"]).await?;
+    let image = inline_raw(&mut form, "image").unwrap_or_default();
+    let message = inline_raw(&mut form, "message")
+        .ok_or_else(|| inline_error(Error::InvalidArgument("missing message field".to_string())))?;
+    let chunk_type = inline_text(&mut form, "chunk_type")
+        .ok_or_else(|| inline_error(Error::InvalidArgument("missing chunk_type field".to_string())))?;
+    let position = inline_text(&mut form, "position");
+    let force = inline_text(&mut form, "force").is_some_and(|f| f == "true");
+
+    info!("Encoding {} bytes inline as {}", message.len(), chunk_type);
+
+    position
+        .as_deref()
+        .map_or(Ok(Position::Auto), Position::from_str)
+        .and_then(|p| commands::encode_bytes(&image, &chunk_type, &message, &p, force))
+        .map(|png| (ContentType::PNG, png))
+        .map_err(inline_error)
+}
+
+// Inline decode endpoint: /decode/inline
+// Returns the raw data of the selected chunk without storing the uploaded
+// image. Binary payloads cannot be told apart once joined, so an
+// occurrence that selects more than one chunk is refused.
+#[post("/decode/inline", data = "<data>")]
+async fn decode_inline_endpoint(
+    _access: ReadAccess,
+    content_type: &ContentType,
+    data: data::Data<'_>,
+) -> Result<(ContentType, Vec<u8>), Custom<Json<PrintResponse>>> {
+    let mut form = parse_inline_form(content_type, data, &["chunk_type", "occurrence"]).await?;
+    let image = inline_raw(&mut form, "image").unwrap_or_default();
+    let chunk_type = inline_text(&mut form, "chunk_type")
+        .ok_or_else(|| inline_error(Error::InvalidArgument("missing chunk_type field".to_string())))?;
+    let occurrence = inline_text(&mut form, "occurrence");
+
+    info!("Decoding {} inline", chunk_type);
+
+    parse_occurrence(occurrence.as_deref())
+        .and_then(|o| commands::decode_bytes(&image, &chunk_type, &o))
+        .and_then(|mut payloads| match payloads.len() {
+            1 => Ok((ContentType::Binary, payloads.remove(0))),
+            n => Err(Error::InvalidArgument(format!(
+                "{} {} chunks selected; choose one occurrence",
+                n, chunk_type
+            ))),
+        })
+        .map_err(inline_error)
+}
+
+
+// Job endpoints: /jobs/<kind> queue a command and answer 202 with a job
+// that /jobs/<id> reports on until its result is ready.
+#[derive(Serialize, JsonSchema)]
+struct JobResponse {
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<&'static str>,
+    job: Option<JobView>,
+    message: String,
+}
+
+fn submit_job<F>(jobs: &Arc<Jobs>, storage: &Storage, kind: &'static str, work: F) -> Custom<Json<JobResponse>>
+where
+    F: FnOnce(&Task) -> Result<serde_json::Value, Error> + Send + 'static,
+{
+    let submitted = jobs
+        .submit(storage.root(), kind, work)
+        .and_then(|id| jobs.get(storage.root(), &id));
+    match submitted {
+        Some(job) => {
+            let response = JobResponse {
+                status: "success".to_string(),
+                code: None,
+                message: format!("{} job {} queued", kind, job.id),
+                job: Some(job),
+            };
+            Custom(Status::Accepted, Json(response))
+        }
+        None => {
+            let response = JobResponse {
+                status: "error".to_string(),
+                code: Some("queue_full"),
+                job: None,
+                message: "Too many jobs queued, try again later".to_string(),
+            };
+            Custom(Status::ServiceUnavailable, Json(response))
+        }
+    }
+}
+
+// Fails a job once it has been cancelled, before it commits its output
+fn check_cancelled(task: &Task) -> Result<(), Error> {
+    if task.is_cancelled() {
+        return Err(Error::Cancelled);
+    }
+    Ok(())
+}
+
+// Reports progress on the task, and stops the command once it is cancelled.
+fn observe(task: &Task) -> impl FnMut(Progress) -> Result<(), Error> + '_ {
+    move |progress| {
+        task.report(progress);
+        check_cancelled(task)
+    }
+}
+
+#[post("/jobs/embed", data = "<embed_data>")]
+fn embed_job(
+    access: WriteAccess,
+    jobs: &State<Arc<Jobs>>,
+    limit: &State<DecodeLimit>,
+    embed_data: Json<EmbedData>,
+) -> Custom<Json<JobResponse>> {
+    let (storage, limit) = (access.workspace, limit.0);
+    let embed_data = embed_data.into_inner();
+    submit_job(jobs, &storage.clone(), "embed", move |task| {
+        let (_, output_id, backup) = edit_stored(&storage, &embed_data.id, &embed_data.output, |path, output| {
+            commands::embed_with_progress(
+                path,
+                &embed_data.message,
+                &embed_data.key,
+                embed_data.method,
+                limit,
+                output,
+                &mut observe(task),
+            )?;
+            check_cancelled(task)
+        })?;
+        Ok(serde_json::json!({ "id": output_id, "backup": backup }))
+    })
+}
+
+#[post("/jobs/encode", data = "<encode_data>")]
+fn encode_job(access: WriteAccess, jobs: &State<Arc<Jobs>>, encode_data: Json<EncodeData>) -> Custom<Json<JobResponse>> {
+    let storage = access.workspace;
+    let encode_data = encode_data.into_inner();
+    submit_job(jobs, &storage.clone(), "encode", move |task| {
+        let position = encode_data
+            .position
+            .as_deref()
+            .map_or(Ok(Position::Auto), Position::from_str)?;
+        // Cancelling after the write is still in time: edit_stored undoes the
+        // edit, in place or not, when the closure fails.
+        let (_, output_id, backup) = edit_stored(&storage, &encode_data.id, &encode_data.output, |path, output| {
+            commands::encode_with_progress(
+                path,
+                &encode_data.chunk_type,
+                &encode_data.message,
+                &position,
+                encode_data.force,
+                output,
+                &mut observe(task),
+            )?;
+            check_cancelled(task)
+        })?;
+        Ok(serde_json::json!({ "id": output_id, "backup": backup }))
+    })
+}
+
+#[post("/jobs/sanitize", data = "<sanitize_data>")]
+fn sanitize_job(
+    access: WriteAccess,
+    jobs: &State<Arc<Jobs>>,
+    limit: &State<DecodeLimit>,
+    sanitize_data: Json<SanitizeData>,
+) -> Custom<Json<JobResponse>> {
+    let (storage, limit) = (access.workspace, limit.0);
+    let sanitize_data = sanitize_data.into_inner();
+    submit_job(jobs, &storage.clone(), "sanitize", move |task| {
+        let (output_id, report) = sanitize_stored(&storage, &sanitize_data, limit)?;
+        if let Err(e) = check_cancelled(task) {
+            let _ = storage.remove(&output_id);
+            return Err(e);
+        }
+        Ok(serde_json::json!({ "id": output_id, "report": report }))
+    })
+}
+
+#[post("/jobs/decode", data = "<decode_data>")]
+fn decode_job(access: ReadAccess, jobs: &State<Arc<Jobs>>, decode_data: Json<DecodeData>) -> Custom<Json<JobResponse>> {
+    let storage = access.workspace;
+    let decode_data = decode_data.into_inner();
+    submit_job(jobs, &storage.clone(), "decode", move |task| {
+        let occurrence = parse_occurrence(decode_data.occurrence.as_deref())?;
+        let path = resolve(&storage, &decode_data.id)?;
+        let messages = commands::decode_with_progress(&path, &decode_data.chunk_type, &occurrence, &mut observe(task))?;
+        check_cancelled(task)?;
+        Ok(serde_json::json!({ "messages": messages }))
+    })
+}
+
+#[post("/jobs/extract", data = "<extract_data>")]
+fn extract_job(
+    access: ReadAccess,
+    jobs: &State<Arc<Jobs>>,
+    limit: &State<DecodeLimit>,
+    extract_data: Json<ExtractData>,
+) -> Custom<Json<JobResponse>> {
+    let (storage, limit) = (access.workspace, limit.0);
+    let extract_data = extract_data.into_inner();
+    submit_job(jobs, &storage.clone(), "extract", move |task| {
+        let path = resolve(&storage, &extract_data.id)?;
+        let message = commands::extract_with_progress(&path, &extract_data.key, limit, &mut observe(task))?;
+        check_cancelled(task)?;
+        Ok(serde_json::json!({ "message": message }))
+    })
+}
+
+#[post("/jobs/analyze", data = "<analyze_data>")]
+fn analyze_job(
+    access: ReadAccess,
+    jobs: &State<Arc<Jobs>>,
+    limit: &State<DecodeLimit>,
+    analyze_data: Json<PrintData>,
+) -> Custom<Json<JobResponse>> {
+    let (storage, limit) = (access.workspace, limit.0);
+    let analyze_data = analyze_data.into_inner();
+    submit_job(jobs, &storage.clone(), "analyze", move |task| {
+        let path = resolve(&storage, &analyze_data.id)?;
+        let report = commands::analyze_with_progress(&path, limit, &mut observe(task))?;
+        check_cancelled(task)?;
+        Ok(serde_json::json!({ "report": report }))
+    })
+}
+
+fn job_response(job: Option<JobView>, id: &str) -> Custom<Json<JobResponse>> {
+    match job {
+        Some(job) => {
+            let response = JobResponse {
+                status: "success".to_string(),
+                code: None,
+                message: format!("{} job {}", job.kind, job.id),
+                job: Some(job),
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        None => {
+            let response = JobResponse {
+                status: "error".to_string(),
+                code: Some("job_not_found"),
+                job: None,
+                message: format!("No job found with id -: {}", id),
+            };
+            Custom(Status::NotFound, Json(response))
+        }
+    }
+}
+
+#[get("/jobs/<id>")]
+fn job_endpoint(access: ReadAccess, jobs: &State<Arc<Jobs>>, id: &str) -> Custom<Json<JobResponse>> {
+    job_response(jobs.get(access.workspace.root(), id), id)
+}
+
+// Streams the job as server-sent events: a `progress` event whenever it
+// changes, then one `done` event once it has finished.
+#[get("/jobs/<id>/events")]
+fn job_events(access: ReadAccess, jobs: &State<Arc<Jobs>>, id: &str, mut shutdown: Shutdown) -> Option<EventStream![]> {
+    let mut updates = jobs.subscribe(access.workspace.root(), id)?;
+    Some(EventStream! {
+        loop {
+            let job = updates.borrow_and_update().clone();
+            if job.state.is_finished() {
+                yield Event::json(&job).event("done");
+                break;
+            }
+            yield Event::json(&job).event("progress");
+            tokio::select! {
+                changed = updates.changed() => if changed.is_err() { break },
+                _ = &mut shutdown => break,
+            }
+        }
+    })
+}
+
+#[delete("/jobs/<id>")]
+fn cancel_job(access: WriteAccess, jobs: &State<Arc<Jobs>>, id: &str) -> Custom<Json<JobResponse>> {
+    job_response(jobs.cancel(access.workspace.root(), id), id)
+}
+
+// Status endpoint: /status
+#[get("/status")]
+fn status() -> Json<StatusResponse> {
+    Json(StatusResponse {
+        encoding: "online",
+        decoding: "online",
+        metadata: "online",
+    })
+}
+
+#[get("/download/<id>")]
+async fn download_file(access: ReadAccess, id: &str) -> Option<File> {
+    let storage = &access.workspace;
+    storage.take(id).ok().map(File::from_std)
+}
+
+// Delete endpoint: /images/<id>
+#[delete("/images/<id>")]
+fn delete_endpoint(access: WriteAccess, id: &str) -> Custom<Json<PrintResponse>> {
+    let storage = &access.workspace;
+    match storage.remove(id) {
+        Ok(()) => {
+            let response = PrintResponse {
+                status: "success".to_string(),
+                code: None,
+                chunks: None,
+                message: format!("Image {} deleted", id),
+            };
+            Custom(Status::Ok, Json(response))
+        }
+        Err(e) => {
+            error!("Error deleting image: {}", e);
+            let response = PrintResponse {
+                status: "error".to_string(),
+                code: Some(e.code()),
+                chunks: None,
+                message: e.to_string(),
+            };
+            Custom(status_for(&e), Json(response))
+        }
+    }
+}
+
+// Deletes expired images from every workspace in the background, so files
+// of users who never come back do not pile up.
+fn cleanup() -> AdHoc {
+    AdHoc::on_liftoff("Expired image cleanup", |rocket| {
+        Box::pin(async move {
+            let tokens = rocket.state::<Arc<Tokens>>().cloned();
+            let storage = rocket.state::<Storage>().cloned();
+            let (Some(tokens), Some(storage)) = (tokens, storage) else {
+                return;
+            };
+            let mut shutdown = rocket.shutdown();
+            tokio::spawn(async move {
+                let mut interval = tokio::time::interval(tokens.cleanup_interval());
+                loop {
+                    tokio::select! {
+                        _ = interval.tick() => {}
+                        _ = &mut shutdown => break,
+                    }
+                    let (tokens, storage) = (tokens.clone(), storage.clone());
+                    match tokio::task::spawn_blocking(move || tokens.sweep(&storage)).await {
+                        Ok(Ok(expired)) => {
+                            if expired.deleted > 0 {
+                                info!("Deleted {} expired images", expired.deleted);
+                            }
+                            for (id, e) in expired.failures {
+                                error!("Error deleting expired image {}: {}", id, e);
+                            }
+                        }
+                        Ok(Err(e)) => error!("Error deleting expired images: {}", e),
+                        Err(e) => error!("Cleanup task failed: {}", e),
+                    }
+                }
+            });
+        })
+    })
+}
+
+// Bytes an image may take up once decoded, from the `decode_limit` setting,
+// so uploads claiming huge dimensions are refused before inflating.
+struct DecodeLimit(usize);
+
+fn decode_limit() -> AdHoc {
+    AdHoc::try_on_ignite("Decode limit", |rocket| async move {
+        if rocket.figment().find_value("decode_limit").is_err() {
+            return Ok(rocket.manage(DecodeLimit(DEFAULT_DECODE_LIMIT)));
+        }
+        match rocket.figment().extract_inner::<ByteUnit>("decode_limit") {
+            Ok(limit) => {
+                let limit = limit.as_u64().try_into().unwrap_or(usize::MAX);
+                Ok(rocket.manage(DecodeLimit(limit)))
+            }
+            Err(e) => {
+                error!("Invalid decode_limit: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+// Refused by the ReadAccess or WriteAccess guard
+#[catch(401)]
+fn unauthorized(request: &Request<'_>) -> Json<PrintResponse> {
+    refused(request)
+}
+
+#[catch(403)]
+fn forbidden(request: &Request<'_>) -> Json<PrintResponse> {
+    refused(request)
+}
+
+fn refused(request: &Request<'_>) -> Json<PrintResponse> {
+    Json(PrintResponse {
+        status: "error".to_string(),
+        code: Some("auth_failure"),
+        chunks: None,
+        message: auth::refusal(request),
+    })
+}
+
+// Handle preflight requests
+#[options("/<_..>")]
+fn all_options() -> &'static str {
+    ""
+}
+
+// OpenAPI document: /openapi.json
+// Built once at ignition from the mounted routes, see `api`.
+struct OpenApiDocument(String);
+
+#[get("/openapi.json")]
+fn openapi_endpoint(document: &State<OpenApiDocument>) -> RawJson<String> {
+    RawJson(document.0.clone())
+}
+
+// Deprecated routes, answered with `Deprecation` headers
+struct Deprecated(Vec<openapi::RouteKey>);
+
+fn openapi(operations: HashMap<openapi::RouteKey, Operation>) -> AdHoc {
+    AdHoc::try_on_ignite("OpenAPI", |rocket| async move {
+        match openapi::document(rocket.routes(), &operations) {
+            Ok(document) => {
+                let document = serde_json::to_string_pretty(&document).unwrap_or_default();
+                let deprecated = operations
+                    .iter()
+                    .filter(|(_, operation)| operation.successor().is_some())
+                    .map(|(key, _)| key.clone())
+                    .collect();
+                Ok(rocket.manage(OpenApiDocument(document)).manage(Deprecated(deprecated)))
+            }
+            Err(e) => {
+                error!("Invalid OpenAPI description -: {}", e);
+                Err(rocket)
+            }
+        }
+    })
+}
+
+// Marks answers from deprecated routes, pointing at the document that names
+// their /api/v1 successors.
+fn deprecation() -> AdHoc {
+    AdHoc::on_response("Deprecation", |request, response| {
+        Box::pin(async move {
+            let key = request.route().map(openapi::route_key);
+            let deprecated = request.rocket().state::<Deprecated>();
+            if key.is_some_and(|key| deprecated.is_some_and(|d| d.0.contains(&key))) {
+                response.set_header(Header::new("Deprecation", "true"));
+                response.set_header(Header::new("Link", "</openapi.json>; rel=\"deprecation\""));
+            }
+        })
+    })
+}
+
+// Every route mounted at `/`, each with what /openapi.json says about it.
+// `openapi.json` at the repository root has to match the document built
+// from these; see `test_openapi_document_is_current`.
+fn api() -> Api {
+    Api::new("/")
+        .route(routes![index], Operation::new("Greets the caller").returns_raw("text/plain"))
+        .route(routes![status], Operation::new("Reports which features are online").returns::<StatusResponse>())
+        .route(routes![openapi_endpoint], Operation::new("This document").returns_raw("application/json"))
+        .route(
+            routes![upload],
+            Operation::new("Stores an image in the caller's workspace and returns its ID")
+                .write()
+                .form_body(&["ttl", "once"])
+                .returns::<UploadResponse>()
+                .deprecated_by("/api/v1/images"),
+        )
+        .route(
+            routes![download_file],
+            Operation::new("Downloads a stored image")
+                .read()
+                .param::<String>("id", "ID of a stored image")
+                .returns_raw("application/octet-stream")
+                .deprecated_by("/api/v1/images/{id}"),
+        )
+        .route(
+            routes![images_endpoint],
+            Operation::new("Lists the caller's workspace with its usage and quota")
+                .read()
+                .returns::<ImagesResponse>()
+                .deprecated_by("/api/v1/images"),
+        )
+        .route(
+            routes![delete_endpoint],
+            Operation::new("Deletes a stored image")
+                .write()
+                .param::<String>("id", "ID of a stored image")
+                .returns::<PrintResponse>()
+                .deprecated_by("/api/v1/images/{id}"),
+        )
+        .route(
+            routes![encode_endpoint],
+            Operation::new("Adds a chunk holding a message")
+                .write()
+                .json_body::<EncodeData>()
+                .returns::<EditResponse>()
+                .deprecated_by("/api/v1/images/{id}/chunks"),
+        )
+        .route(
+            routes![decode_endpoint],
+            Operation::new("Reads the messages of a chunk type")
+                .read()
+                .json_body::<DecodeData>()
+                .returns::<DecodeResponse>()
+                .deprecated_by("/api/v1/images/{id}/chunks/{chunk_type}"),
+        )
+        .route(
+            routes![remove_endpoint],
+            Operation::new("Removes chunks of a type")
+                .write()
+                .json_body::<RemoveData>()
+                .returns::<EditResponse>()
+                .deprecated_by("/api/v1/images/{id}/chunks/{chunk_type}"),
+        )
+        .route(
+            routes![print_endpoint],
+            Operation::new("Lists every chunk of an image")
+                .read()
+                .json_body::<PrintData>()
+                .returns::<PrintResponse>()
+                .deprecated_by("/api/v1/images/{id}/chunks"),
+        )
+        .route(
+            routes![list_endpoint],
+            Operation::new("Lists the chunks of one type with their sizes")
+                .read()
+                .json_body::<ListData>()
+                .returns::<ListResponse>()
+                .deprecated_by("/api/v1/images/{id}/chunks"),
+        )
+        .route(
+            routes![audit_endpoint],
+            Operation::new("Checks an image for hiding places and anomalies")
+                .read()
+                .json_body::<PrintData>()
+                .returns::<AuditResponse>()
+                .deprecated_by("/api/v1/images/{id}/audit"),
+        )
+        .route(
+            routes![analyze_endpoint],
+            Operation::new("Estimates whether the pixels carry hidden data")
+                .read()
+                .json_body::<PrintData>()
+                .returns::<AnalyzeResponse>()
+                .deprecated_by("/api/v1/images/{id}/analysis"),
+        )
+        .route(
+            routes![bitplane_endpoint],
+            Operation::new("Renders one bit plane of a channel as a PNG")
+                .read()
+                .param::<String>("id", "ID of a stored image")
+                .param::<Option<usize>>("channel", "Channel index, 0 when left out")
+                .param::<Option<u8>>("bit", "Bit index from 0, the least significant, to 7")
+                .param::<Option<String>>("diff", "ID of a second image to XOR the plane against")
+                .returns_raw("image/png")
+                .deprecated_by("/api/v1/images/{id}/bitplane"),
+        )
+        .route(
+            routes![embed_endpoint],
+            Operation::new("Hides a message in the pixels under a key, into a new image unless in_place is set")
+                .write()
+                .json_body::<EmbedData>()
+                .returns::<EditResponse>()
+                .deprecated_by("/api/v1/images/{id}/payload"),
+        )
+        .route(
+            routes![extract_endpoint],
+            Operation::new("Reads a message hidden in the pixels")
+                .read()
+                .json_body::<ExtractData>()
+                .returns::<PrintResponse>()
+                .deprecated_by("/api/v1/images/{id}/payload"),
+        )
+        .route(
+            routes![sanitize_endpoint],
+            Operation::new("Strips ancillary chunks, trailing data and optionally pixel LSBs into a new image")
+                .write()
+                .json_body::<SanitizeData>()
+                .returns::<SanitizeResponse>(),
+        )
+        .route(
+            routes![repair_endpoint],
+            Operation::new("Recovers what it can from a damaged image")
+                .write()
+                .json_body::<RepairData>()
+                .returns::<RepairResponse>(),
+        )
+        .route(
+            routes![overlay_read_endpoint],
+            Operation::new("Reads the data after IEND")
+                .read()
+                .json_body::<OverlayData>()
+                .returns::<PrintResponse>()
+                .deprecated_by("/api/v1/images/{id}/overlay"),
+        )
+        .route(
+            routes![overlay_write_endpoint],
+            Operation::new("Replaces the data after IEND")
+                .write()
+                .json_body::<OverlayData>()
+                .returns::<EditResponse>()
+                .deprecated_by("/api/v1/images/{id}/overlay"),
+        )
+        .route(
+            routes![overlay_remove_endpoint],
+            Operation::new("Removes the data after IEND")
+                .write()
+                .json_body::<OverlayData>()
+                .returns::<EditResponse>()
+                .deprecated_by("/api/v1/images/{id}/overlay"),
+        )
+        .route(
+            routes![encode_inline_endpoint],
+            Operation::new("Adds a chunk to an uploaded image and returns it without storing anything")
+                .write()
+                .form_body(&["message", "chunk_type", "position", "force"])
+                .returns_raw("image/png"),
+        )
+        .route(
+            routes![decode_inline_endpoint],
+            Operation::new("Reads a chunk of an uploaded image without storing it")
+                .read()
+                .form_body(&["chunk_type", "occurrence"])
+                .returns_raw("application/octet-stream"),
+        )
+        .route(
+            routes![embed_job],
+            Operation::new("Queues an embed, into a new image unless in_place is set")
+                .write()
+                .json_body::<EmbedData>()
+                .status(202)
+                .returns::<JobResponse>(),
+        )
+        .route(
+            routes![encode_job],
+            Operation::new("Queues an encode")
+                .write()
+                .json_body::<EncodeData>()
+                .status(202)
+                .returns::<JobResponse>(),
+        )
+        .route(
+            routes![sanitize_job],
+            Operation::new("Queues a sanitize")
+                .write()
+                .json_body::<SanitizeData>()
+                .status(202)
+                .returns::<JobResponse>(),
+        )
+        .route(
+            routes![decode_job],
+            Operation::new("Queues a decode")
+                .read()
+                .json_body::<DecodeData>()
+                .status(202)
+                .returns::<JobResponse>(),
+        )
+        .route(
+            routes![extract_job],
+            Operation::new("Queues an extract")
+                .read()
+                .json_body::<ExtractData>()
+                .status(202)
+                .returns::<JobResponse>(),
+        )
+        .route(
+            routes![analyze_job],
+            Operation::new("Queues an analysis")
+                .read()
+                .json_body::<PrintData>()
+                .status(202)
+                .returns::<JobResponse>(),
+        )
+        .route(
+            routes![job_endpoint],
+            Operation::new("Reports a job's state, progress and result")
+                .read()
+                .param::<String>("id", "ID of a job")
+                .returns::<JobResponse>(),
+        )
+        .route(
+            routes![job_events],
+            Operation::new("Streams a job's progress as server-sent events, ending with a `done` event")
+                .read()
+                .param::<String>("id", "ID of a job")
+                .returns_raw("text/event-stream"),
+        )
+        .route(
+            routes![cancel_job],
+            Operation::new("Cancels a job")
+                .write()
+                .param::<String>("id", "ID of a job")
+                .returns::<JobResponse>(),
+        )
+}
+
+// Build the Rocket server from a configuration figment
+pub fn rocket(figment: Figment, storage: Storage) -> Rocket<Build> {
+
+    let (routes, mut operations) = api().into_parts();
+    let (v1_routes, v1_operations) = v1::api().into_parts();
+    operations.extend(v1_operations);
+
+    rocket::custom(figment)
+    .mount("/", routes)
+    // CORS preflight, which the OpenAPI document leaves out
+    .mount("/", routes![all_options])
+    .mount("/api/v1", v1_routes)
+    .register("/", catchers![unauthorized, forbidden])
+    .register("/api/v1", catchers![v1::fallback])
+    .attach(auth::fairing())
+    .attach(cleanup())
+    .attach(decode_limit())
+    .attach(jobs::fairing())
+    .attach(openapi(operations))
+    .attach(deprecation())
+    .attach(Cors)
+    .manage(storage)
+}
+
+// Launch the Rocket server with command line overrides on top of Rocket.toml
+pub async fn serve(host: Option<String>, port: Option<u16>, storage_dir: PathBuf, profile: Option<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut figment = rocket::Config::figment();
+    if let Some(profile) = profile {
+        figment = figment.select(profile);
+    }
+    if let Some(host) = host {
+        figment = figment.merge(("address", host));
+    }
+    if let Some(port) = port {
+        figment = figment.merge(("port", port));
+    }
+
+    rocket(figment, Storage::open(storage_dir)?).launch().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use rocket::http::Header;
+    use rocket::local::blocking::Client;
+    use tempfile::TempDir;
+
+    // A client on a fresh storage directory, removed when the returned
+    // `TempDir` is dropped at the end of the test.
+    fn client(name: &str) -> (Client, PathBuf, TempDir) {
+        configured_client(name, |figment| figment)
+    }
+
+    fn configured_client(name: &str, configure: impl FnOnce(Figment) -> Figment) -> (Client, PathBuf, TempDir) {
+        let dir = testing::temp_dir(name);
+        let storage = Storage::open(dir.path()).unwrap();
+        // Tests look at the workspace shared by the writer and reader tokens.
+        let root = storage.workspace("alice").unwrap().root().to_path_buf();
+        let figment = rocket::Config::figment()
+            .merge((
+                "auth.tokens",
+                serde_json::json!([
+                    { "token": "writer", "user": "alice", "scopes": ["write"] },
+                    { "token": "reader", "user": "alice", "scopes": ["read"] },
+                    { "token": "other", "user": "bob", "scopes": ["write"] },
+                ]),
+            ))
+            .merge(("auth.users.bob.quota", "1 KiB"))
+            .merge(("auth.cors_origins", ["http://allowed.test"]));
+        (Client::tracked(rocket(configure(figment), storage)).unwrap(), root, dir)
+    }
+
+    fn bearer(token: &str) -> Header<'static> {
+        Header::new("Authorization", format!("Bearer {}", token))
+    }
+
+    fn upload_body(file_name: &str, bytes: &[u8]) -> Vec<u8> {
+        let mut body = format!(
+            "--BOUNDARY\r\nContent-Disposition: form-data; name=\"image\"; filename=\"{}\"\r\nContent-Type: image/png\r\n\r\n",
+            file_name
+        )
+        .into_bytes();
+        body.extend(bytes);
+        body.extend(b"\r\n--BOUNDARY--\r\n");
+        body
+    }
+
+    #[test]
+    fn test_upload_ignores_client_file_name() {
+        let (client, root, _dir) = client("upload");
+        let escape = root.parent().unwrap().join("pngme-escaped.png");
+        let response = client
+            .post("/upload")
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY")))
+            .body(upload_body("../pngme-escaped.png", b"not really a png"))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        let id = body["id"].as_str().unwrap();
+        assert!(!escape.exists());
+        assert_eq!(fs::read(root.join(id)).unwrap(), b"not really a png");
+
+        let download = client.get(format!("/download/{}", id)).header(bearer("reader")).dispatch();
+        assert_eq!(download.into_bytes().unwrap(), b"not really a png");
+    }
+
+    fn inline_body(image: &[u8], fields: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut body = upload_body("carrier.png", image);
+        body.truncate(body.len() - "--BOUNDARY--\r\n".len());
+        for (name, value) in fields {
+            body.extend(format!("--BOUNDARY\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n", name).bytes());
+            body.extend(*value);
+            body.extend(b"\r\n");
+        }
+        body.extend(b"--BOUNDARY--\r\n");
+        body
+    }
+
+    #[test]
+    fn test_inline_encode_and_decode() {
+        let (client, root, _dir) = client("inline");
+        let form = ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY"));
+        let carrier = testing::carrier();
+
+        let response = client
+            .post("/encode/inline")
+            .header(form.clone())
+            .body(inline_body(&carrier, &[("chunk_type", b"ruSt"), ("message", b"secret\xff")]))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::PNG));
+        let encoded = response.into_bytes().unwrap();
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+
+        let response = client
+            .post("/decode/inline")
+            .header(form.clone())
+            .body(inline_body(&encoded, &[("chunk_type", b"ruSt")]))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_bytes().unwrap(), b"secret\xff");
+
+        let twice = commands::encode_bytes(&encoded, "ruSt", b"again", &Position::End, false).unwrap();
+        let response = client
+            .post("/decode/inline")
+            .header(form.clone())
+            .body(inline_body(&twice, &[("chunk_type", b"ruSt"), ("occurrence", b"all")]))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(response.status(), Status::BadRequest);
+        let response = client
+            .post("/decode/inline")
+            .header(form.clone())
+            .body(inline_body(&twice, &[("chunk_type", b"ruSt"), ("occurrence", b"1")]))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(response.into_bytes().unwrap(), b"again");
+
+        let response = client
+            .post("/decode/inline")
+            .header(form)
+            .body(inline_body(&carrier, &[("chunk_type", b"ruSt")]))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(fs::read_dir(&root).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_encode_stores_a_new_image() {
+        let (client, root, _dir) = client("edit");
+        let carrier = testing::carrier();
+        let storage = Storage::open(&root).unwrap();
+        let id = storage.create(&carrier).unwrap();
+
+        let response = client
+            .post("/encode")
+            .json(&serde_json::json!({ "id": id, "chunk_type": "ruSt", "message": "hi" }))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        let output_id = body["id"].as_str().unwrap();
+        assert_ne!(output_id, id);
+        assert_eq!(fs::read(root.join(&id)).unwrap(), carrier);
+
+        let response = client
+            .post("/remove")
+            .json(&serde_json::json!({ "id": output_id, "chunk_type": "ruSt", "in_place": true, "backup": true }))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["id"], output_id);
+        assert_eq!(fs::read(root.join(output_id)).unwrap(), carrier);
+        let backup = body["backup"].as_str().unwrap();
+        assert_ne!(fs::read(root.join(backup)).unwrap(), carrier);
+
+        let count = fs::read_dir(&root).unwrap().count();
+        let response = client
+            .post("/remove")
+            .json(&serde_json::json!({ "id": id, "chunk_type": "ruSt" }))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(fs::read_dir(&root).unwrap().count(), count);
+    }
+
+    #[test]
+    fn test_paths_are_rejected() {
+        let (client, root, _dir) = client("paths");
+        let outside = root.parent().unwrap().join("pngme-outside.png");
+        fs::write(&outside, b"outside").unwrap();
+
+        for id in ["../pngme-outside.png", outside.to_str().unwrap(), "Cargo.toml"] {
+            let response = client
+                .post("/print")
+                .json(&serde_json::json!({ "id": id }))
+                .header(bearer("writer"))
+                .dispatch();
+            assert_eq!(response.status(), Status::BadRequest, "{}", id);
+        }
+        let response = client.get("/download/..%2Fpngme-outside.png").header(bearer("reader")).dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+        fs::remove_file(outside).unwrap();
+    }
+
+    #[test]
+    fn test_tokens_and_scopes() {
+        let (client, _root, _dir) = client("auth");
+        let print = |token: Option<&str>| {
+            let request = client.post("/print").json(&serde_json::json!({ "id": "missing" }));
+            match token {
+                Some(token) => request.header(bearer(token)).dispatch().status(),
+                None => request.dispatch().status(),
+            }
+        };
+        assert_eq!(print(None), Status::Unauthorized);
+        assert_eq!(print(Some("guess")), Status::Unauthorized);
+        assert_eq!(print(Some("reader")), Status::BadRequest);
+        assert_eq!(print(Some("writer")), Status::BadRequest);
+
+        let response = client
+            .post("/upload")
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY")))
+            .header(bearer("reader"))
+            .body(upload_body("image.png", b"png"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["code"], "auth_failure");
+
+        assert_eq!(client.get("/status").dispatch().status(), Status::Ok);
+    }
+
+    #[test]
+    fn test_cors_allow_list() {
+        let (client, _root, _dir) = client("cors");
+        let response = client.get("/status").header(Header::new("Origin", "http://allowed.test")).dispatch();
+        assert_eq!(
+            response.headers().get_one("Access-Control-Allow-Origin"),
+            Some("http://allowed.test")
+        );
+        let allowed = response.headers().get_one("Access-Control-Allow-Headers").unwrap();
+        assert!(allowed.contains("Payload-Key"));
+        assert_eq!(
+            response.headers().get_one("Access-Control-Expose-Headers"),
+            Some("Location, Deprecation, Link")
+        );
+
+        let response = client.get("/status").header(Header::new("Origin", "http://evil.test")).dispatch();
+        assert_eq!(response.headers().get_one("Access-Control-Allow-Origin"), None);
+        let response = client.options("/encode").header(Header::new("Origin", "http://evil.test")).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn test_workspaces_are_isolated() {
+        let (client, _root, _dir) = client("workspaces");
+        let form = ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY"));
+        let upload = |token: &str, bytes: &[u8]| {
+            client
+                .post("/upload")
+                .header(form.clone())
+                .header(bearer(token))
+                .body(upload_body("image.png", bytes))
+                .dispatch()
+        };
+        let body: serde_json::Value = upload("writer", b"alice's image").into_json().unwrap();
+        let id = body["id"].as_str().unwrap().to_string();
+
+        let download = client.get(format!("/download/{}", id)).header(bearer("other")).dispatch();
+        assert_eq!(download.status(), Status::NotFound);
+        let response = client
+            .post("/print")
+            .header(bearer("other"))
+            .json(&serde_json::json!({ "id": id }))
+            .dispatch();
+        assert_eq!(response.status(), Status::NotFound);
+
+        let images: serde_json::Value = client.get("/images").header(bearer("reader")).dispatch().into_json().unwrap();
+        assert_eq!(images["images"][0]["id"], id.as_str());
+        assert_eq!(images["usage"], 13);
+        let images: serde_json::Value = client.get("/images").header(bearer("other")).dispatch().into_json().unwrap();
+        assert_eq!(images["images"], serde_json::json!([]));
+        assert_eq!(images["quota"], 1024);
+
+        assert_eq!(upload("other", &[0; 1000]).status(), Status::Ok);
+        let response = upload("other", &[0; 100]);
+        assert_eq!(response.status(), Status::InsufficientStorage);
+        let body: serde_json::Value = response.into_json().unwrap();
+        assert_eq!(body["code"], "quota_exceeded");
+    }
+
+    #[test]
+    fn test_in_place_edit_over_quota_is_refused() {
+        let (client, _root, _dir) = client("in-place-quota");
+        let body: serde_json::Value = client
+            .post("/upload")
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY")))
+            .header(bearer("other"))
+            .body(upload_body("image.png", &testing::carrier()))
+            .dispatch()
+            .into_json()
+            .unwrap();
+        let id = body["id"].as_str().unwrap().to_string();
+        let overlay = |message: &str| {
+            client
+                .post("/overlay/write")
+                .header(bearer("other"))
+                .json(&serde_json::json!({ "id": id, "message": message, "in_place": true }))
+                .dispatch()
+        };
+
+        let response = overlay(&"x".repeat(2000));
+        assert_eq!(response.status(), Status::InsufficientStorage);
+        let download = client.get(format!("/download/{}", id)).header(bearer("other")).dispatch();
+        assert_eq!(download.into_bytes().unwrap(), testing::carrier());
+
+        assert_eq!(overlay("fits").status(), Status::Ok);
+        let images: serde_json::Value = client.get("/images").header(bearer("other")).dispatch().into_json().unwrap();
+        assert_eq!(images["images"].as_array().unwrap().len(), 1);
+        assert_eq!(images["usage"], testing::carrier().len() + 4);
+    }
+
+    #[test]
+    fn test_expiry_and_delete() {
+        let (client, _root, _dir) = client("expiry");
+        let upload = |fields: &[(&str, &[u8])]| {
+            let body: serde_json::Value = client
+                .post("/upload")
+                .header(ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY")))
+                .header(bearer("writer"))
+                .body(inline_body(b"image", fields))
+                .dispatch()
+                .into_json()
+                .unwrap();
+            body["id"].as_str().map(str::to_string)
+        };
+
+        let once = upload(&[("once", b"true")]).unwrap();
+        let download = |id: &str| client.get(format!("/download/{}", id)).header(bearer("reader")).dispatch();
+        assert_eq!(download(&once).into_bytes().unwrap(), b"image");
+        assert_eq!(download(&once).status(), Status::NotFound);
+
+        let ttl = upload(&[("ttl", b"60")]).unwrap();
+        let images: serde_json::Value = client.get("/images").header(bearer("reader")).dispatch().into_json().unwrap();
+        let expires_at = images["images"][0]["expires_at"].as_u64().unwrap();
+        assert!(expires_at <= storage::now() + 60);
+        assert!(upload(&[("ttl", b"soon")]).is_none());
+
+        let delete = |token: &str| client.delete(format!("/images/{}", ttl)).header(bearer(token)).dispatch().status();
+        assert_eq!(delete("reader"), Status::Forbidden);
+        assert_eq!(delete("writer"), Status::Ok);
+        assert_eq!(delete("writer"), Status::NotFound);
+        assert_eq!(download(&ttl).status(), Status::NotFound);
+    }
+
+    #[test]
+    fn test_jobs_queue_and_report() {
+        let (client, root, _dir) = client("jobs");
+        let carrier = testing::carrier();
+        let id = Storage::open(&root).unwrap().create(&carrier).unwrap();
+
+        let response = client
+            .post("/jobs/encode")
+            .json(&serde_json::json!({ "id": id, "chunk_type": "ruSt", "message": "hi" }))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Accepted);
+        let body: serde_json::Value = response.into_json().unwrap();
+        let job = body["job"]["id"].as_str().unwrap().to_string();
+        assert_eq!(body["job"]["kind"], "encode");
+
+        let poll = |token: &str| client.get(format!("/jobs/{}", job)).header(bearer(token)).dispatch();
+        assert_eq!(poll("other").status(), Status::NotFound);
+        let cancel = client.delete(format!("/jobs/{}", job)).header(bearer("reader")).dispatch();
+        assert_eq!(cancel.status(), Status::Forbidden);
+        let job = loop {
+            let body: serde_json::Value = poll("reader").into_json().unwrap();
+            if !matches!(body["job"]["state"].as_str(), Some("queued" | "running")) {
+                break body["job"].clone();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        };
+        assert_eq!(job["state"], "succeeded");
+        let output_id = job["result"]["id"].as_str().unwrap();
+        assert_ne!(output_id, id);
+        assert_ne!(fs::read(root.join(output_id)).unwrap(), carrier);
+
+        let response = client
+            .post("/jobs/encode")
+            .json(&serde_json::json!({ "id": id, "chunk_type": "ruSt", "message": "hi" }))
+            .header(bearer("reader"))
+            .dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn test_job_events_stream_progress() {
+        let (client, root, _dir) = client("events");
+        let pixels = pngme::pixels::Pixels::new(8, 8, 8, 2, vec![90; 8 * 8 * 3]).unwrap();
+        let id = Storage::open(&root).unwrap().create(&pngme::Png::from_pixels(&pixels).as_bytes()).unwrap();
+
+        let response = client
+            .post("/jobs/analyze")
+            .json(&serde_json::json!({ "id": id }))
+            .header(bearer("reader"))
+            .dispatch();
+        let body: serde_json::Value = response.into_json().unwrap();
+        let job = body["job"]["id"].as_str().unwrap().to_string();
+
+        let events = |token: &str| client.get(format!("/jobs/{}/events", job)).header(bearer(token)).dispatch();
+        assert_eq!(events("other").status(), Status::NotFound);
+        let response = events("reader");
+        assert_eq!(response.content_type(), Some(ContentType::EventStream));
+        let stream = response.into_string().unwrap();
+        let (last_event, last_data) = stream
+            .split("\n\n")
+            .filter(|event| !event.is_empty())
+            .map(|event| {
+                let field = |name: &str| event.lines().find_map(|l| l.strip_prefix(name)).unwrap().to_string();
+                (field("event:"), field("data:"))
+            })
+            .last()
+            .unwrap();
+        assert_eq!(last_event, "done");
+        let done: serde_json::Value = serde_json::from_str(&last_data).unwrap();
+        assert_eq!(done["state"], "succeeded");
+        assert_eq!(done["progress"]["stage"], "analyzing");
+        assert_eq!(done["progress"]["channels_analyzed"], 3);
+        assert!(done["result"]["report"]["verdict"].is_string());
+    }
+
+    #[test]
+    fn test_v1_resources() {
+        let (client, _root, _dir) = client("v1");
+        let pixels = pngme::pixels::Pixels::new(8, 8, 8, 2, vec![90; 8 * 8 * 3]).unwrap();
+        let image = pngme::Png::from_pixels(&pixels).as_bytes();
+
+        let refused = client.get("/api/v1/images").dispatch();
+        assert_eq!(refused.status(), Status::Unauthorized);
+        let body: serde_json::Value = refused.into_json().unwrap();
+        assert_eq!(body["code"], "auth_failure");
+
+        let created = client
+            .post("/api/v1/images")
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY")))
+            .body(upload_body("cover.png", &image))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(created.status(), Status::Created);
+        let location = created.headers().get_one("Location").unwrap().to_string();
+        let body: serde_json::Value = created.into_json().unwrap();
+        assert_eq!(location, format!("/api/v1/images/{}", body["id"].as_str().unwrap()));
+        let downloaded = client.get(location.clone()).header(bearer("reader")).dispatch();
+        assert_eq!(downloaded.content_type(), Some(ContentType::PNG));
+        assert_eq!(downloaded.into_bytes().unwrap(), image);
+
+        let text = client
+            .post("/api/v1/images")
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY")))
+            .body(upload_body("notes.txt", b"not an image"))
+            .header(bearer("writer"))
+            .dispatch();
+        let text = text.headers().get_one("Location").unwrap().to_string();
+        let downloaded = client.get(text).header(bearer("reader")).dispatch();
+        assert_eq!(downloaded.content_type(), Some(ContentType::Binary));
+
+        let copied = client
+            .post(format!("/api/v1/images?source={}", body["id"].as_str().unwrap()))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(copied.status(), Status::Created);
+        let copy = copied.headers().get_one("Location").unwrap().to_string();
+        assert_ne!(copy, location);
+
+        let added = client
+            .post(format!("{}/chunks", copy))
+            .json(&serde_json::json!({ "chunk_type": "ruSt", "message": "secret" }))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(added.status(), Status::Created);
+        let chunk_location = added.headers().get_one("Location").unwrap().to_string();
+        assert_eq!(chunk_location, format!("{}/chunks/ruSt", copy));
+        let original = client.get(format!("{}/chunks/ruSt", location)).header(bearer("reader")).dispatch();
+        assert_eq!(original.status(), Status::NotFound);
+
+        let chunk = client.get(chunk_location).header(bearer("reader")).dispatch();
+        let body: serde_json::Value = chunk.into_json().unwrap();
+        assert_eq!(body["messages"], serde_json::json!(["secret"]));
+        let chunks = client.get(format!("{}/chunks?chunk_type=ruSt", copy)).header(bearer("reader")).dispatch();
+        let body: serde_json::Value = chunks.into_json().unwrap();
+        assert_eq!(body["chunks"].as_array().unwrap().len(), 1);
+
+        let embedded = client
+            .put(format!("{}/payload", copy))
+            .json(&serde_json::json!({ "message": "hi", "key": "key" }))
+            .header(bearer("writer"))
+            .dispatch();
+        assert_eq!(embedded.status(), Status::NoContent);
+        let keyless = client.get(format!("{}/payload", copy)).header(bearer("reader")).dispatch();
+        assert_eq!(keyless.status(), Status::BadRequest);
+        let payload = client
+            .get(format!("{}/payload", copy))
+            .header(bearer("reader"))
+            .header(Header::new("Payload-Key", "key"))
+            .dispatch();
+        let body: serde_json::Value = payload.into_json().unwrap();
+        assert_eq!(body["message"], "hi");
+
+        let removed = client.delete(format!("{}/chunks/ruSt", copy)).header(bearer("writer")).dispatch();
+        assert_eq!(removed.status(), Status::Ok);
+        let body: serde_json::Value = removed.into_json().unwrap();
+        assert_eq!(body["removed"], 1);
+        let missing = client.get(format!("{}/chunks/ruSt", copy)).header(bearer("reader")).dispatch();
+        assert_eq!(missing.status(), Status::NotFound);
+
+        let deleted = client.delete(location.clone()).header(bearer("writer")).dispatch();
+        assert_eq!(deleted.status(), Status::NoContent);
+        let gone = client.get(location).header(bearer("reader")).dispatch();
+        assert_eq!(gone.status(), Status::NotFound);
+        let body: serde_json::Value = gone.into_json().unwrap();
+        assert_eq!(body["code"], "file_not_found");
+        let unknown = client.get("/api/v1/nothing").dispatch();
+        let body: serde_json::Value = unknown.into_json().unwrap();
+        assert_eq!(body["code"], "not_found");
+
+        let legacy = client.get("/images").header(bearer("reader")).dispatch();
+        assert_eq!(legacy.headers().get_one("Deprecation"), Some("true"));
+        let current = client.get("/status").dispatch();
+        assert_eq!(current.headers().get_one("Deprecation"), None);
+    }
+
+    #[test]
+    fn test_decode_limit_is_configured() {
+        let (limited, _root, _dir) = configured_client("limit", |figment| figment.merge(("decode_limit", "64 B")));
+        let pixels = pngme::pixels::Pixels::new(8, 8, 8, 2, vec![90; 8 * 8 * 3]).unwrap();
+        let image = pngme::Png::from_pixels(&pixels).as_bytes();
+        let created = limited
+            .post("/api/v1/images")
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY")))
+            .body(upload_body("cover.png", &image))
+            .header(bearer("writer"))
+            .dispatch();
+        let location = created.headers().get_one("Location").unwrap().to_string();
+
+        let refused = limited.get(format!("{}/analysis", location)).header(bearer("reader")).dispatch();
+        assert_eq!(refused.status(), Status::UnprocessableEntity);
+        let body: serde_json::Value = refused.into_json().unwrap();
+        assert!(body["message"].as_str().unwrap().contains("over the limit of 64"));
+
+        let (client, _root, _dir) = client("default_limit");
+        let created = client
+            .post("/api/v1/images")
+            .header(ContentType::new("multipart", "form-data").with_params(("boundary", "BOUNDARY")))
+            .body(upload_body("cover.png", &image))
+            .header(bearer("writer"))
+            .dispatch();
+        let location = created.headers().get_one("Location").unwrap().to_string();
+        let analyzed = client.get(format!("{}/analysis", location)).header(bearer("reader")).dispatch();
+        assert_eq!(analyzed.status(), Status::Ok);
+    }
+
+    // Set PNGME_UPDATE_OPENAPI=1 to rewrite openapi.json after changing a
+    // route or one of its types.
+    #[test]
+    fn test_openapi_document_is_current() {
+        let (client, _root, _dir) = client("openapi");
+        let response = client.get("/openapi.json").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+        let document = response.into_string().unwrap();
+
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi.json");
+        if std::env::var_os("PNGME_UPDATE_OPENAPI").is_some() {
+            fs::write(path, format!("{}\n", document)).unwrap();
+        }
+        let committed = fs::read_to_string(path).unwrap_or_default();
+        assert!(
+            committed.trim_end() == document,
+            "openapi.json is out of date; rerun with PNGME_UPDATE_OPENAPI=1 and commit the result"
+        );
+    }
+}
@@ -0,0 +1,491 @@
+// Version 1 of the REST API, mounted at /api/v1.
+//
+// Images, their chunks, pixel payload and overlay are resources addressed by
+// path: GET reads, POST creates, PUT replaces and DELETE removes. Each
+// operation answers with its own type, and every failure with `ApiError`.
+// Edits change the addressed image itself; to keep the original, first copy
+// it with `POST /images?source=<id>` and edit the copy.
+
+use super::{edit_stored, parse_occurrence, resolve, status_for, store_upload, DecodeLimit, OutputData};
+use crate::auth::{self, ReadAccess, WriteAccess};
+use crate::openapi::{Api, Operation};
+use pngme::analysis::AnalysisReport;
+use pngme::audit::AuditReport;
+use pngme::png::{ChunkInfo, Position};
+use pngme::storage::StoredFile;
+use pngme::{commands, lsb, Error, Png};
+use rocket::data::{self, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::request::{self, FromRequest};
+use rocket::response::status::{Created, Custom, NoContent};
+use rocket::serde::{json::Json, Deserialize, Serialize};
+use rocket::{catch, delete, error, get, post, put, routes, Request, State};
+use schemars::JsonSchema;
+use std::io::{Read, Seek};
+use std::str::FromStr;
+use tokio::fs::File;
+
+/// Why a request failed.
+#[derive(Serialize, JsonSchema)]
+pub struct ApiError {
+    /// Machine-readable reason, such as `chunk_not_found`
+    code: &'static str,
+    message: String,
+}
+
+type Failure = Custom<Json<ApiError>>;
+
+fn fail(e: Error) -> Failure {
+    error!("Error handling request: {}", e);
+    Custom(status_for(&e), Json(ApiError { code: e.code(), message: e.to_string() }))
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ImageList {
+    images: Vec<StoredFile>,
+    /// Bytes stored in the workspace
+    usage: u64,
+    /// Bytes the workspace may hold, if limited
+    quota: Option<u64>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct NewImage {
+    id: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ChunkList {
+    chunks: Vec<ChunkInfo>,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ChunkMessages {
+    chunk_type: String,
+    messages: Vec<String>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct NewChunk {
+    chunk_type: String,
+    message: String,
+    /// `auto` (the default), `end`, an index, `before:TYPE` or `after:TYPE`
+    #[serde(default)]
+    position: Option<String>,
+    /// Allow critical, public and reserved chunk types
+    #[serde(default)]
+    force: bool,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ChunkAdded {
+    chunk_type: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct ChunksRemoved {
+    chunk_type: String,
+    removed: usize,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct NewPayload {
+    message: String,
+    key: String,
+    #[serde(default)]
+    method: lsb::PixelMethod,
+}
+
+#[derive(Serialize, JsonSchema)]
+struct Payload {
+    message: String,
+}
+
+/// The `Payload-Key` header, kept out of the URL so keys do not end up in
+/// access logs. Missing keys are reported by the handler as an `ApiError`.
+struct PayloadKey(Option<String>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for PayloadKey {
+    type Error = ();
+
+    async fn from_request(request: &'r Request<'_>) -> request::Outcome<Self, ()> {
+        request::Outcome::Success(PayloadKey(request.headers().get_one("Payload-Key").map(str::to_string)))
+    }
+}
+
+const IN_PLACE: OutputData = OutputData { in_place: true, backup: false };
+
+async fn blocking<T: Send + 'static>(
+    work: impl FnOnce() -> Result<T, Error> + Send + 'static,
+) -> Result<T, Failure> {
+    super::blocking(work).await.map_err(fail)
+}
+
+#[get("/images")]
+fn list_images(access: ReadAccess) -> Result<Json<ImageList>, Failure> {
+    let storage = &access.workspace;
+    let images = storage.list().map_err(fail)?;
+    let usage = images.iter().map(|image| image.size).sum();
+    Ok(Json(ImageList { images, usage, quota: storage.quota() }))
+}
+
+#[post("/images?<source>", data = "<data>")]
+async fn create_image(
+    access: WriteAccess,
+    source: Option<&str>,
+    content_type: Option<&ContentType>,
+    data: data::Data<'_>,
+) -> Result<Created<Json<NewImage>>, Failure> {
+    let stored = match source {
+        Some(source) => {
+            let (storage, source) = (access.workspace, source.to_string());
+            blocking(move || storage.copy(&source)).await
+        }
+        None => store_upload(&access.workspace, content_type.unwrap_or(&ContentType::Any), data)
+            .await
+            .map_err(|(status, code, message)| Custom(status, Json(ApiError { code, message }))),
+    };
+    stored.map(|id| Created::new(format!("/api/v1/images/{}", id)).body(Json(NewImage { id })))
+}
+
+// Uploads are stored as sent, so only files starting with the PNG
+// signature are served as `image/png`.
+#[get("/images/<id>")]
+async fn get_image(access: ReadAccess, id: &str) -> Result<(ContentType, File), Failure> {
+    let (storage, id) = (access.workspace, id.to_string());
+    let (is_png, file) = blocking(move || {
+        let mut file = storage.take(&id)?;
+        let mut signature = [0; 8];
+        let is_png = file.read_exact(&mut signature).is_ok() && signature == Png::STANDARD_HEADER;
+        file.rewind()?;
+        Ok((is_png, file))
+    })
+    .await?;
+    let content_type = if is_png { ContentType::PNG } else { ContentType::Binary };
+    Ok((content_type, File::from_std(file)))
+}
+
+#[delete("/images/<id>")]
+fn delete_image(access: WriteAccess, id: &str) -> Result<NoContent, Failure> {
+    access.workspace.remove(id).map_err(fail)?;
+    Ok(NoContent)
+}
+
+#[get("/images/<id>/analysis")]
+async fn get_analysis(access: ReadAccess, limit: &State<DecodeLimit>, id: &str) -> Result<Json<AnalysisReport>, Failure> {
+    let (storage, limit, id) = (access.workspace, limit.0, id.to_string());
+    blocking(move || commands::analyze(&resolve(&storage, &id)?, limit)).await.map(Json)
+}
+
+#[get("/images/<id>/audit")]
+async fn get_audit(access: ReadAccess, id: &str) -> Result<Json<AuditReport>, Failure> {
+    let (storage, id) = (access.workspace, id.to_string());
+    blocking(move || commands::audit(&resolve(&storage, &id)?)).await.map(Json)
+}
+
+#[get("/images/<id>/bitplane?<channel>&<bit>&<diff>")]
+async fn get_bitplane(
+    access: ReadAccess,
+    limit: &State<DecodeLimit>,
+    id: &str,
+    channel: Option<usize>,
+    bit: Option<u8>,
+    diff: Option<&str>,
+) -> Result<(ContentType, Vec<u8>), Failure> {
+    let (storage, limit, id, diff) = (access.workspace, limit.0, id.to_string(), diff.map(str::to_string));
+    let rendered = blocking(move || {
+        let path = resolve(&storage, &id)?;
+        let diff = diff.map(|diff| resolve(&storage, &diff)).transpose()?;
+        commands::render_bitplane(&path, channel.unwrap_or(0), bit.unwrap_or(0), diff.as_deref(), limit)
+    });
+    rendered.await.map(|bytes| (ContentType::PNG, bytes))
+}
+
+#[get("/images/<id>/chunks?<chunk_type>")]
+async fn list_chunks(access: ReadAccess, id: &str, chunk_type: Option<&str>) -> Result<Json<ChunkList>, Failure> {
+    let (storage, id, chunk_type) = (access.workspace, id.to_string(), chunk_type.map(str::to_string));
+    let chunks = blocking(move || {
+        let path = resolve(&storage, &id)?;
+        match chunk_type {
+            Some(chunk_type) => commands::list(&path, &chunk_type),
+            None => commands::chunks(&path),
+        }
+    });
+    chunks.await.map(|chunks| Json(ChunkList { chunks }))
+}
+
+#[post("/images/<id>/chunks", data = "<new_chunk>")]
+async fn add_chunk(access: WriteAccess, id: &str, new_chunk: Json<NewChunk>) -> Result<Created<Json<ChunkAdded>>, Failure> {
+    let (storage, source, new_chunk) = (access.workspace, id.to_string(), new_chunk.into_inner());
+    let chunk_type = new_chunk.chunk_type.clone();
+    blocking(move || {
+        let position = new_chunk.position.as_deref().map_or(Ok(Position::Auto), Position::from_str)?;
+        edit_stored(&storage, &source, &IN_PLACE, |path, output| {
+            commands::encode(path, &new_chunk.chunk_type, &new_chunk.message, &position, new_chunk.force, output)
+        })
+    })
+    .await?;
+    let location = format!("/api/v1/images/{}/chunks/{}", id, chunk_type);
+    Ok(Created::new(location).body(Json(ChunkAdded { chunk_type })))
+}
+
+#[get("/images/<id>/chunks/<chunk_type>?<occurrence>")]
+async fn get_chunk(
+    access: ReadAccess,
+    id: &str,
+    chunk_type: &str,
+    occurrence: Option<&str>,
+) -> Result<Json<ChunkMessages>, Failure> {
+    let occurrence = parse_occurrence(occurrence).map_err(fail)?;
+    let (storage, id, chunk_type) = (access.workspace, id.to_string(), chunk_type.to_string());
+    blocking(move || {
+        let messages = commands::decode(&resolve(&storage, &id)?, &chunk_type, &occurrence)?;
+        Ok(Json(ChunkMessages { chunk_type, messages }))
+    })
+    .await
+}
+
+#[delete("/images/<id>/chunks/<chunk_type>?<occurrence>")]
+async fn delete_chunk(
+    access: WriteAccess,
+    id: &str,
+    chunk_type: &str,
+    occurrence: Option<&str>,
+) -> Result<Json<ChunksRemoved>, Failure> {
+    let occurrence = parse_occurrence(occurrence).map_err(fail)?;
+    let (storage, source, chunk_type) = (access.workspace, id.to_string(), chunk_type.to_string());
+    blocking(move || {
+        let (removed, _, _) = edit_stored(&storage, &source, &IN_PLACE, |path, output| {
+            commands::remove(path, &chunk_type, &occurrence, output)
+        })?;
+        Ok(Json(ChunksRemoved { chunk_type, removed }))
+    })
+    .await
+}
+
+#[put("/images/<id>/payload", data = "<payload>")]
+async fn put_payload(
+    access: WriteAccess,
+    limit: &State<DecodeLimit>,
+    id: &str,
+    payload: Json<NewPayload>,
+) -> Result<NoContent, Failure> {
+    let (storage, limit, source, payload) = (access.workspace, limit.0, id.to_string(), payload.into_inner());
+    blocking(move || {
+        edit_stored(&storage, &source, &IN_PLACE, |path, output| {
+            commands::embed(path, &payload.message, &payload.key, payload.method, limit, output)
+        })
+    })
+    .await?;
+    Ok(NoContent)
+}
+
+#[get("/images/<id>/payload")]
+async fn get_payload(
+    access: ReadAccess,
+    limit: &State<DecodeLimit>,
+    id: &str,
+    key: PayloadKey,
+) -> Result<Json<Payload>, Failure> {
+    let Some(key) = key.0 else {
+        return Err(fail(Error::InvalidArgument("the Payload-Key header is required".to_string())));
+    };
+    let (storage, limit, id) = (access.workspace, limit.0, id.to_string());
+    let message = blocking(move || commands::extract(&resolve(&storage, &id)?, &key, limit)).await?;
+    Ok(Json(Payload { message }))
+}
+
+#[get("/images/<id>/overlay")]
+async fn get_overlay(access: ReadAccess, id: &str) -> Result<(ContentType, Vec<u8>), Failure> {
+    let (storage, id) = (access.workspace, id.to_string());
+    let overlay = blocking(move || commands::read_overlay(&resolve(&storage, &id)?)).await?;
+    Ok((ContentType::Binary, overlay))
+}
+
+#[put("/images/<id>/overlay", data = "<data>")]
+async fn put_overlay(access: WriteAccess, id: &str, data: data::Data<'_>) -> Result<NoContent, Failure> {
+    let overlay = data.open(32.mebibytes()).into_bytes().await.map_err(|e| fail(e.into()))?;
+    if !overlay.is_complete() {
+        return Err(fail(Error::InvalidArgument("overlay larger than 32 MiB".to_string())));
+    }
+    let (storage, source) = (access.workspace, id.to_string());
+    blocking(move || {
+        edit_stored(&storage, &source, &IN_PLACE, |path, output| {
+            commands::write_overlay(path, &overlay, output)
+        })
+    })
+    .await?;
+    Ok(NoContent)
+}
+
+#[delete("/images/<id>/overlay")]
+async fn delete_overlay(access: WriteAccess, id: &str) -> Result<NoContent, Failure> {
+    let (storage, source) = (access.workspace, id.to_string());
+    blocking(move || {
+        edit_stored(&storage, &source, &IN_PLACE, |path, output| {
+            commands::write_overlay(path, &[], output)
+        })
+    })
+    .await?;
+    Ok(NoContent)
+}
+
+// Answers requests no handler took, including those the auth guards refused,
+// with an `ApiError` like the handlers' own failures.
+#[catch(default)]
+pub fn fallback(status: Status, request: &Request<'_>) -> Json<ApiError> {
+    let (code, message) = match status.code {
+        401 | 403 => ("auth_failure", auth::refusal(request)),
+        404 => ("not_found", format!("no resource at {}", request.uri().path())),
+        _ => ("invalid_request", status.reason_lossy().to_string()),
+    };
+    Json(ApiError { code, message })
+}
+
+// The routes mounted at `/api/v1`, each with what /openapi.json says about it.
+pub fn api() -> Api {
+    Api::new("/api/v1")
+        .route(
+            routes![list_images],
+            Operation::new("Lists the caller's workspace with its usage and quota")
+                .read()
+                .returns::<ImageList>()
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![create_image],
+            Operation::new("Stores an uploaded image, or a copy of a stored one, in the caller's workspace")
+                .write()
+                .param::<Option<String>>("source", "ID of a stored image to copy instead of uploading one")
+                .form_body(&["ttl", "once"])
+                .status(201)
+                .returns::<NewImage>()
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![get_image],
+            Operation::new("Downloads a stored image; a file that is not a PNG comes as application/octet-stream")
+                .read()
+                .param::<String>("id", "ID of a stored image")
+                .returns_raw("image/png")
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![delete_image],
+            Operation::new("Deletes a stored image")
+                .write()
+                .param::<String>("id", "ID of a stored image")
+                .status(204)
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![get_analysis],
+            Operation::new("Estimates whether the pixels carry hidden data")
+                .read()
+                .param::<String>("id", "ID of a stored image")
+                .returns::<AnalysisReport>()
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![get_audit],
+            Operation::new("Checks an image for hiding places and anomalies")
+                .read()
+                .param::<String>("id", "ID of a stored image")
+                .returns::<AuditReport>()
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![get_bitplane],
+            Operation::new("Renders one bit plane of a channel as a PNG")
+                .read()
+                .param::<String>("id", "ID of a stored image")
+                .param::<Option<usize>>("channel", "Channel index, 0 when left out")
+                .param::<Option<u8>>("bit", "Bit index from 0, the least significant, to 7")
+                .param::<Option<String>>("diff", "ID of a second image to XOR the plane against")
+                .returns_raw("image/png")
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![list_chunks],
+            Operation::new("Lists the chunks of an image with their sizes")
+                .read()
+                .param::<String>("id", "ID of a stored image")
+                .param::<Option<String>>("chunk_type", "Only list chunks of this type")
+                .returns::<ChunkList>()
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![add_chunk],
+            Operation::new("Adds a chunk holding a message")
+                .write()
+                .param::<String>("id", "ID of a stored image")
+                .json_body::<NewChunk>()
+                .status(201)
+                .returns::<ChunkAdded>()
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![get_chunk],
+            Operation::new("Reads the messages of a chunk type")
+                .read()
+                .param::<String>("id", "ID of a stored image")
+                .param::<String>("chunk_type", "Four-letter chunk type")
+                .param::<Option<String>>("occurrence", "`first` (the default), `all`, an index or `START..END`")
+                .returns::<ChunkMessages>()
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![delete_chunk],
+            Operation::new("Removes chunks of a type")
+                .write()
+                .param::<String>("id", "ID of a stored image")
+                .param::<String>("chunk_type", "Four-letter chunk type")
+                .param::<Option<String>>("occurrence", "`first` (the default), `all`, an index or `START..END`")
+                .returns::<ChunksRemoved>()
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![put_payload],
+            Operation::new("Hides a message in the pixels under a key")
+                .write()
+                .param::<String>("id", "ID of a stored image")
+                .json_body::<NewPayload>()
+                .status(204)
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![get_payload],
+            Operation::new("Reads a message hidden in the pixels")
+                .read()
+                .param::<String>("id", "ID of a stored image")
+                .header::<String>("Payload-Key", "Key the message was hidden under")
+                .returns::<Payload>()
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![get_overlay],
+            Operation::new("Reads the data after IEND")
+                .read()
+                .param::<String>("id", "ID of a stored image")
+                .returns_raw("application/octet-stream")
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![put_overlay],
+            Operation::new("Replaces the data after IEND with the request body")
+                .write()
+                .param::<String>("id", "ID of a stored image")
+                .raw_body("application/octet-stream")
+                .status(204)
+                .errors::<ApiError>(),
+        )
+        .route(
+            routes![delete_overlay],
+            Operation::new("Removes the data after IEND")
+                .write()
+                .param::<String>("id", "ID of a stored image")
+                .status(204)
+                .errors::<ApiError>(),
+        )
+}
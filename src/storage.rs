@@ -0,0 +1,544 @@
+use crate::Error;
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Files kept under one root directory and addressed by server-generated IDs.
+///
+/// Callers never supply a path: an ID is 32 lowercase hex digits, so it
+/// cannot name anything outside the root, and symlinks inside the root are
+/// refused in case something else placed them there.
+///
+/// A storage may be split into named workspaces, each a storage of its own
+/// with an optional quota and retention period.
+#[derive(Clone, Debug)]
+pub struct Storage {
+    root: PathBuf,
+    quota: Option<u64>,
+    retention: Option<Duration>,
+}
+
+/// A stored file, for listing a workspace.
+#[derive(Debug, Serialize)]
+#[cfg_attr(feature = "server", derive(schemars::JsonSchema))]
+pub struct StoredFile {
+    pub id: String,
+    pub size: u64,
+    /// Last write, in seconds since the Unix epoch
+    pub modified: u64,
+    /// When the file will be deleted, in seconds since the Unix epoch
+    pub expires_at: Option<u64>,
+    /// Whether the file is deleted once downloaded
+    pub once: bool,
+}
+
+/// What a pass of [`Storage::expire`] deleted, and what it could not.
+#[derive(Debug, Default)]
+pub struct Expired {
+    pub deleted: usize,
+    /// Files that could not be deleted, by ID, with the reason
+    pub failures: Vec<(String, Error)>,
+}
+
+impl Expired {
+    /// Adds the outcome of another pass, say over another workspace.
+    pub fn merge(&mut self, other: Expired) {
+        self.deleted += other.deleted;
+        self.failures.extend(other.failures);
+    }
+}
+
+/// When a stored file is deleted, overriding the retention period. Kept in
+/// a `{id}.expiry` file next to it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Expiry {
+    /// Seconds since the Unix epoch after which the file is deleted
+    pub expires_at: Option<u64>,
+    /// Delete the file when it is first taken with [`Storage::take`]
+    pub once: bool,
+}
+
+impl Expiry {
+    fn parse(text: &str) -> Expiry {
+        let mut expiry = Expiry::default();
+        for line in text.lines() {
+            match line.split_once(' ') {
+                Some(("expires", at)) => expiry.expires_at = at.parse().ok(),
+                _ if line == "once" => expiry.once = true,
+                _ => {}
+            }
+        }
+        expiry
+    }
+
+    fn to_text(self) -> String {
+        let mut text = String::new();
+        if let Some(at) = self.expires_at {
+            text.push_str(&format!("expires {}\n", at));
+        }
+        if self.once {
+            text.push_str("once\n");
+        }
+        text
+    }
+}
+
+/// Seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Checks `name` can name a workspace: 1 to 64 ASCII letters, digits, `-`
+/// or `_`.
+pub fn check_workspace_name(name: &str) -> Result<(), Error> {
+    let valid = (1..=64).contains(&name.len())
+        && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_');
+    if !valid {
+        return Err(Error::InvalidArgument(format!("invalid workspace name {:?}", name)));
+    }
+    Ok(())
+}
+
+/// When the file described by `metadata` was last written, in seconds
+/// since the Unix epoch.
+fn modified_secs(metadata: &fs::Metadata) -> Result<u64, Error> {
+    Ok(metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs())
+}
+
+fn is_id(name: &str) -> bool {
+    name.len() == 32 && name.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+impl Storage {
+    /// Opens `root`, creating it if needed.
+    pub fn open(root: impl AsRef<Path>) -> Result<Storage, Error> {
+        fs::create_dir_all(&root)?;
+        Ok(Storage {
+            root: root.as_ref().canonicalize()?,
+            quota: None,
+            retention: None,
+        })
+    }
+
+    /// Opens the workspace `name` under this storage, creating it if needed.
+    pub fn workspace(&self, name: &str) -> Result<Storage, Error> {
+        check_workspace_name(name)?;
+        let root = self.root.join(name);
+        if fs::symlink_metadata(&root).is_ok_and(|m| !m.is_dir()) {
+            return Err(Error::InvalidArgument(format!("workspace {} is not a directory", name)));
+        }
+        Storage::open(root)
+    }
+
+    /// Limits the total size of the stored files to `quota` bytes.
+    pub fn with_quota(mut self, quota: Option<u64>) -> Storage {
+        self.quota = quota;
+        self
+    }
+
+    /// Lets [`Storage::expire`] delete files not written for `retention`.
+    pub fn with_retention(mut self, retention: Option<Duration>) -> Storage {
+        self.retention = retention;
+        self
+    }
+
+    pub fn quota(&self) -> Option<u64> {
+        self.quota
+    }
+
+    pub fn retention(&self) -> Option<Duration> {
+        self.retention
+    }
+
+    /// The names of the workspaces under this storage.
+    pub fn workspaces(&self) -> Result<Vec<String>, Error> {
+        let mut names = vec![];
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.file_type()?.is_dir() && check_workspace_name(&name).is_ok() {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Stores `bytes` as a new file and returns its ID.
+    pub fn create(&self, bytes: &[u8]) -> Result<String, Error> {
+        self.check_quota(bytes.len() as u64)?;
+        let (id, path) = self.reserve()?;
+        fs::write(path, bytes)?;
+        Ok(id)
+    }
+
+    /// Stores a copy of `id` under a new ID and returns it. The copy reads
+    /// `id` like [`Storage::take`] does, and gets no expiry of its own.
+    pub fn copy(&self, id: &str) -> Result<String, Error> {
+        let mut source = self.take(id)?;
+        let (copy_id, path) = self.reserve()?;
+        std::io::copy(&mut source, &mut File::create(&path)?)
+            .inspect_err(|_| { let _ = fs::remove_file(&path); })?;
+        self.admit(&copy_id)?;
+        Ok(copy_id)
+    }
+
+    /// Confirms the storage is within quota now that a command has written
+    /// `id`, deleting `id` if it is not.
+    pub fn admit(&self, id: &str) -> Result<(), Error> {
+        self.check_quota(0).inspect_err(|_| {
+            let _ = fs::remove_file(self.root.join(id));
+        })
+    }
+
+    /// Confirms the storage is within quota after a command rewrote a file
+    /// in place. Unlike [`Storage::admit`] nothing is deleted; the caller
+    /// puts the original back.
+    pub fn check_usage(&self) -> Result<(), Error> {
+        self.check_quota(0)
+    }
+
+    fn check_quota(&self, adding: u64) -> Result<(), Error> {
+        let Some(quota) = self.quota else {
+            return Ok(());
+        };
+        let needed = self.usage()? + adding;
+        if needed > quota {
+            return Err(Error::QuotaExceeded { needed, quota });
+        }
+        Ok(())
+    }
+
+    /// Total size of the stored files in bytes, counting expired files
+    /// until they are deleted.
+    pub fn usage(&self) -> Result<u64, Error> {
+        Ok(self.files()?.iter().map(|file| file.size).sum())
+    }
+
+    /// Every stored file that has not expired, oldest first.
+    pub fn list(&self) -> Result<Vec<StoredFile>, Error> {
+        let now = now();
+        let mut files = self.files()?;
+        files.retain(|file| file.expires_at.is_none_or(|at| at > now));
+        Ok(files)
+    }
+
+    /// Every stored file, expired or not, oldest first.
+    fn files(&self) -> Result<Vec<StoredFile>, Error> {
+        let mut files = vec![];
+        for entry in fs::read_dir(&self.root)? {
+            let entry = entry?;
+            let id = entry.file_name().to_string_lossy().into_owned();
+            let metadata = entry.metadata()?;
+            if !is_id(&id) || !metadata.is_file() {
+                continue;
+            }
+            let modified = modified_secs(&metadata)?;
+            let expiry = self.read_expiry(&id)?;
+            let expires_at = self.expires_at(&expiry, modified);
+            files.push(StoredFile {
+                id,
+                size: metadata.len(),
+                modified,
+                expires_at,
+                once: expiry.once,
+            });
+        }
+        files.sort_by(|a, b| a.modified.cmp(&b.modified).then_with(|| a.id.cmp(&b.id)));
+        Ok(files)
+    }
+
+    /// Deletes files past their expiry, or older than the retention period
+    /// if they have none. A file that cannot be deleted, say because a
+    /// download took it meanwhile, is returned among the failures rather
+    /// than ending the pass.
+    pub fn expire(&self) -> Result<Expired, Error> {
+        let now = now();
+        let mut expired = Expired::default();
+        for file in self.files()? {
+            if file.expires_at.is_some_and(|at| at <= now) {
+                match self.remove(&file.id) {
+                    Ok(()) => expired.deleted += 1,
+                    Err(e) => expired.failures.push((file.id, e)),
+                }
+            }
+        }
+        Ok(expired)
+    }
+
+    /// Overrides when `id` is deleted.
+    pub fn set_expiry(&self, id: &str, expiry: Expiry) -> Result<(), Error> {
+        self.path(id)?;
+        fs::write(self.root.join(format!("{}.expiry", id)), expiry.to_text())?;
+        Ok(())
+    }
+
+    pub fn expiry(&self, id: &str) -> Result<Expiry, Error> {
+        self.path(id)?;
+        self.read_expiry(id)
+    }
+
+    /// When a file with `expiry`, last written at `modified`, is deleted.
+    fn expires_at(&self, expiry: &Expiry, modified: u64) -> Option<u64> {
+        expiry
+            .expires_at
+            .or_else(|| self.retention.map(|r| modified.saturating_add(r.as_secs())))
+    }
+
+    fn read_expiry(&self, id: &str) -> Result<Expiry, Error> {
+        match fs::read_to_string(self.root.join(format!("{}.expiry", id))) {
+            Ok(text) => Ok(Expiry::parse(&text)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Expiry::default()),
+            Err(e) => Err(Error::Io(e)),
+        }
+    }
+
+    /// Deletes the stored file `id`.
+    pub fn remove(&self, id: &str) -> Result<(), Error> {
+        fs::remove_file(self.locate(id)?.0)?;
+        let _ = fs::remove_file(self.root.join(format!("{}.expiry", id)));
+        Ok(())
+    }
+
+    /// Opens `id` for reading, deleting it at once if it is only to be read
+    /// once. Of several concurrent takes of such a file only one succeeds.
+    pub fn take(&self, id: &str) -> Result<File, Error> {
+        let path = self.path(id)?;
+        if !self.read_expiry(id)?.once {
+            return Ok(File::open(path)?);
+        }
+        let taken = self.root.join(format!("{}.taken", id));
+        fs::rename(&path, &taken).map_err(|_| Error::FileNotFound(id.to_string()))?;
+        let file = File::open(&taken);
+        let _ = fs::remove_file(&taken);
+        let _ = fs::remove_file(self.root.join(format!("{}.expiry", id)));
+        Ok(file?)
+    }
+
+    /// Creates an empty file under a fresh ID for a command to write its
+    /// output to, returning the ID and the file's path.
+    pub fn reserve(&self) -> Result<(String, PathBuf), Error> {
+        loop {
+            let id = format!("{:032x}", rand::random::<u128>());
+            let path = self.root.join(&id);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => return Ok((id, path)),
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(Error::Io(e)),
+            }
+        }
+    }
+
+    /// The path of the stored file `id`. A file past its expiry is not
+    /// found, even before [`Storage::expire`] deletes it.
+    pub fn path(&self, id: &str) -> Result<PathBuf, Error> {
+        let (path, metadata) = self.locate(id)?;
+        let expires_at = self.expires_at(&self.read_expiry(id)?, modified_secs(&metadata)?);
+        if expires_at.is_some_and(|at| at <= now()) {
+            return Err(Error::FileNotFound(id.to_string()));
+        }
+        Ok(path)
+    }
+
+    /// The path and metadata of the stored file `id`, expired or not.
+    fn locate(&self, id: &str) -> Result<(PathBuf, fs::Metadata), Error> {
+        if !is_id(id) {
+            return Err(Error::InvalidArgument(format!("invalid storage id {:?}", id)));
+        }
+        let path = self.root.join(id);
+        let not_found = || Error::FileNotFound(id.to_string());
+        let metadata = fs::symlink_metadata(&path).map_err(|_| not_found())?;
+        if !metadata.is_file() {
+            return Err(not_found());
+        }
+        // The ID check already rules this out; it guards against a root
+        // that is itself swapped for a symlink after startup.
+        if !path.canonicalize()?.starts_with(&self.root) {
+            return Err(not_found());
+        }
+        Ok((path, metadata))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use tempfile::TempDir;
+
+    fn storage(name: &str) -> (TempDir, Storage) {
+        let dir = testing::temp_dir(name);
+        let storage = Storage::open(dir.path()).unwrap();
+        (dir, storage)
+    }
+
+    #[test]
+    fn test_create_and_resolve() {
+        let (_dir, storage) = storage("create");
+        let id = storage.create(b"image").unwrap();
+        assert_eq!(id.len(), 32);
+        assert_eq!(fs::read(storage.path(&id).unwrap()).unwrap(), b"image");
+        assert_ne!(storage.create(b"other").unwrap(), id);
+        assert!(matches!(
+            storage.path(&"0".repeat(32)),
+            Err(Error::FileNotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_traversal_and_absolute_paths() {
+        let (_dir, storage) = storage("traversal");
+        let secret = storage.root().parent().unwrap().join("pngme-secret.png");
+        fs::write(&secret, b"secret").unwrap();
+
+        for id in [
+            "../pngme-secret.png",
+            "..",
+            "",
+            secret.to_str().unwrap(),
+            "/etc/passwd",
+            "0123456789abcdef0123456789abcde/",
+            "0123456789ABCDEF0123456789ABCDEF",
+        ] {
+            assert!(storage.path(id).is_err(), "{:?} was accepted", id);
+        }
+        fs::remove_file(secret).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_rejects_symlinks() {
+        let (_dir, storage) = storage("symlink");
+        let id = "0123456789abcdef0123456789abcdef";
+        std::os::unix::fs::symlink("/etc/hostname", storage.root().join(id)).unwrap();
+        assert!(storage.path(id).is_err());
+
+        let dir = "fedcba9876543210fedcba9876543210";
+        fs::create_dir(storage.root().join(dir)).unwrap();
+        assert!(storage.path(dir).is_err());
+    }
+
+    #[test]
+    fn test_workspaces_are_separate() {
+        let (_dir, storage) = storage("workspaces");
+        let alice = storage.workspace("alice").unwrap();
+        let bob = storage.workspace("bob").unwrap();
+        let id = alice.create(b"image").unwrap();
+        assert!(alice.path(&id).is_ok());
+        assert!(matches!(bob.path(&id), Err(Error::FileNotFound(_))));
+        assert!(storage.path(&id).is_err());
+        assert_eq!(alice.list().unwrap().len(), 1);
+        assert!(bob.list().unwrap().is_empty());
+
+        for name in ["", "..", "a/b", "../bob", &"a".repeat(65)] {
+            assert!(storage.workspace(name).is_err(), "{:?} was accepted", name);
+        }
+    }
+
+    #[test]
+    fn test_quota() {
+        let (_dir, storage) = storage("quota");
+        let storage = storage.with_quota(Some(10));
+        storage.create(b"123456").unwrap();
+        assert!(matches!(
+            storage.create(b"123456"),
+            Err(Error::QuotaExceeded { needed: 12, quota: 10 })
+        ));
+        assert_eq!(storage.usage().unwrap(), 6);
+
+        let (id, path) = storage.reserve().unwrap();
+        fs::write(&path, b"123456").unwrap();
+        assert!(storage.admit(&id).is_err());
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_expire() {
+        let (_dir, storage) = storage("expire");
+        let id = storage.create(b"image").unwrap();
+        assert_eq!(storage.expire().unwrap().deleted, 0);
+
+        let storage = storage.with_retention(Some(Duration::from_secs(3600)));
+        assert_eq!(storage.expire().unwrap().deleted, 0);
+        let old = SystemTime::now() - Duration::from_secs(7200);
+        File::options()
+            .write(true)
+            .open(storage.path(&id).unwrap())
+            .unwrap()
+            .set_modified(old)
+            .unwrap();
+        assert_eq!(storage.expire().unwrap().deleted, 1);
+        assert!(storage.path(&id).is_err());
+    }
+
+    #[test]
+    fn test_expiry_overrides_retention() {
+        let (_dir, storage) = storage("expiry");
+        let storage = storage.with_retention(Some(Duration::from_secs(3600)));
+        let kept = storage.create(b"kept").unwrap();
+        let soon = storage.create(b"soon").unwrap();
+        storage
+            .set_expiry(&soon, Expiry { expires_at: Some(now() - 1), once: false })
+            .unwrap();
+        // Refused at once, though only the next pass deletes it.
+        assert_eq!(storage.list().unwrap().len(), 1);
+        assert!(matches!(storage.path(&soon), Err(Error::FileNotFound(_))));
+        assert_eq!(storage.usage().unwrap(), 8);
+        assert_eq!(storage.expire().unwrap().deleted, 1);
+        assert!(storage.path(&soon).is_err());
+        assert!(!storage.root().join(format!("{}.expiry", soon)).exists());
+        assert!(storage.list().unwrap()[0].expires_at.unwrap() > now());
+
+        storage.remove(&kept).unwrap();
+        assert!(storage.list().unwrap().is_empty());
+        assert!(storage.remove(&kept).is_err());
+    }
+
+    #[test]
+    fn test_take_once() {
+        let (_dir, storage) = storage("once");
+        let id = storage.create(b"secret").unwrap();
+        storage.set_expiry(&id, Expiry { expires_at: None, once: true }).unwrap();
+        assert!(storage.expiry(&id).unwrap().once);
+
+        let mut contents = String::new();
+        std::io::Read::read_to_string(&mut storage.take(&id).unwrap(), &mut contents).unwrap();
+        assert_eq!(contents, "secret");
+        assert!(storage.take(&id).is_err());
+        assert_eq!(fs::read_dir(storage.root()).unwrap().count(), 0);
+
+        let id = storage.create(b"kept").unwrap();
+        storage.take(&id).unwrap();
+        assert!(storage.take(&id).is_ok());
+    }
+
+    #[test]
+    fn test_copy() {
+        let (_dir, storage) = storage("copy");
+        let storage = storage.with_quota(Some(10));
+        let id = storage.create(b"image").unwrap();
+        let copy = storage.copy(&id).unwrap();
+        assert_ne!(copy, id);
+        assert_eq!(fs::read(storage.path(&copy).unwrap()).unwrap(), b"image");
+        assert!(matches!(storage.copy(&id), Err(Error::QuotaExceeded { .. })));
+        assert_eq!(storage.list().unwrap().len(), 2);
+
+        storage.remove(&copy).unwrap();
+        storage.set_expiry(&id, Expiry { expires_at: None, once: true }).unwrap();
+        let copy = storage.copy(&id).unwrap();
+        assert!(storage.path(&id).is_err());
+        assert!(!storage.expiry(&copy).unwrap().once);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+use crate::Error;
+use std::io::{self, ErrorKind, Read, Write};
+
+/// Reads chunks one at a time from any `Read`, so only the chunk being
+/// looked at is ever held in memory. Every chunk's CRC is checked as it is
+/// read, and reading stops after IEND.
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    done: bool,
+    position: u64,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Consumes and checks the PNG signature.
+    pub fn new(mut reader: R) -> Result<ChunkReader<R>, Error> {
+        let mut header = [0u8; 8];
+        reader.read_exact(&mut header).map_err(|e| match e.kind() {
+            ErrorKind::UnexpectedEof => Error::InvalidSignature,
+            _ => Error::Io(e),
+        })?;
+        if header != Png::STANDARD_HEADER {
+            return Err(Error::InvalidSignature);
+        }
+        Ok(ChunkReader {
+            reader,
+            done: false,
+            position: Png::STANDARD_HEADER.len() as u64,
+        })
+    }
+
+    /// Reads the next chunk, or `None` at IEND or a clean end of input.
+    pub fn next_chunk(&mut self) -> Result<Option<Chunk>, Error> {
+        if self.done {
+            return Ok(None);
+        }
+        let mut prefix = [0u8; 8];
+        let read = read_full(&mut self.reader, &mut prefix)?;
+        if read < prefix.len() {
+            self.done = true;
+            return match read {
+                0 => Ok(None),
+                _ => Err(Error::TruncatedChunk {
+                    needed: 12,
+                    available: read,
+                }),
+            };
+        }
+
+        let length = u32::from_be_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]);
+        let chunk_type = ChunkType::try_from([prefix[4], prefix[5], prefix[6], prefix[7]])?;
+
+        // Read through `take` rather than allocating `length` up front, so a
+        // hostile length field cannot force a huge allocation.
+        let mut data = vec![];
+        (&mut self.reader)
+            .take(length as u64)
+            .read_to_end(&mut data)?;
+        let mut crc = [0u8; 4];
+        let crc_read = read_full(&mut self.reader, &mut crc)?;
+        if data.len() < length as usize || crc_read < crc.len() {
+            self.done = true;
+            return Err(Error::TruncatedChunk {
+                needed: length as usize + 12,
+                available: data.len() + crc_read + 8,
+            });
+        }
+
+        let actual = u32::from_be_bytes(crc);
+        let expected = Chunk::checksum(&chunk_type.bytes(), &data);
+        if actual != expected {
+            self.done = true;
+            return Err(Error::CrcMismatch {
+                chunk_type: chunk_type.to_string(),
+                expected,
+                actual,
+            });
+        }
+
+        self.done = chunk_type.bytes() == *b"IEND";
+        self.position += length as u64 + 12;
+        Ok(Some(Chunk::new(chunk_type, data)))
+    }
+
+    /// Bytes consumed by the signature and the chunks read so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the underlying reader, positioned after the last chunk read.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_chunk().transpose()
+    }
+}
+
+/// Writes a PNG chunk by chunk to any `Write`.
+pub struct ChunkWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Writes the PNG signature.
+    pub fn new(mut writer: W) -> Result<ChunkWriter<W>, Error> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(ChunkWriter { writer })
+    }
+
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<(), Error> {
+        self.writer.write_all(&chunk.length().to_be_bytes())?;
+        self.writer.write_all(&chunk.chunk_type().bytes())?;
+        self.writer.write_all(chunk.data())?;
+        self.writer.write_all(&chunk.crc().to_be_bytes())?;
+        Ok(())
+    }
+
+    /// Flushes and returns the underlying writer.
+    pub fn finish(mut self) -> Result<W, Error> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Like `read_exact`, but reports how many bytes were read before EOF
+/// instead of failing.
+fn read_full<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixels::Pixels;
+    use std::str::FromStr;
+
+    fn image() -> Vec<u8> {
+        let pixels = Pixels::new(4, 4, 8, 0, vec![9; 16]).unwrap();
+        let mut chunks = Png::from_pixels(&pixels).into_chunks();
+        let note = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"note".to_vec());
+        chunks.insert(1, note);
+        Png::from_chunks(chunks).as_bytes()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bytes = image();
+        let reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let mut writer = ChunkWriter::new(vec![]).unwrap();
+        for chunk in reader {
+            writer.write_chunk(&chunk.unwrap()).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_stops_after_iend() {
+        let mut bytes = image();
+        bytes.extend(b"trailing bytes");
+        let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+        let types: Vec<String> = reader
+            .by_ref()
+            .map(|c| c.unwrap().chunk_type().to_string())
+            .collect();
+        assert_eq!(types, vec!["IHDR", "ruSt", "IDAT", "IEND"]);
+        assert_eq!(reader.position() as usize, bytes.len() - "trailing bytes".len());
+        assert_eq!(reader.into_inner(), b"trailing bytes");
+    }
+
+    #[test]
+    fn test_bad_input() {
+        assert!(matches!(
+            ChunkReader::new(&[0x89, b'P', b'N'][..]),
+            Err(Error::InvalidSignature)
+        ));
+
+        // Cutting the stream mid-chunk is an error; cutting between chunks
+        // just ends it early.
+        let bytes = image();
+        let mut boundaries = vec![8];
+        for chunk in Png::try_from(bytes.as_slice()).unwrap().chunks() {
+            boundaries.push(boundaries.last().unwrap() + chunk.length() as usize + 12);
+        }
+        for end in 8..bytes.len() {
+            let last = ChunkReader::new(&bytes[..end]).unwrap().last();
+            assert_eq!(matches!(last, Some(Err(_))), !boundaries.contains(&end));
+        }
+
+        let mut corrupt = image();
+        corrupt[20] ^= 1;
+        let mut reader = ChunkReader::new(corrupt.as_slice()).unwrap();
+        assert!(matches!(reader.next(), Some(Err(Error::CrcMismatch { .. }))));
+        assert!(reader.next().is_none());
+    }
+}
@@ -0,0 +1,18 @@
+//! Fixtures shared by the library and server tests; compiled into both.
+
+use pngme::{Chunk, Png};
+use tempfile::TempDir;
+
+/// A fresh directory for the test `name`, deleted with everything in it
+/// when dropped.
+pub fn temp_dir(name: &str) -> TempDir {
+    tempfile::Builder::new()
+        .prefix(&format!("pngme-{}-", name))
+        .tempdir()
+        .unwrap()
+}
+
+/// The smallest image the chunk commands accept: a lone IEND chunk.
+pub fn carrier() -> Vec<u8> {
+    Png::from_chunks(vec![Chunk::new("IEND".parse().unwrap(), vec![])]).as_bytes()
+}
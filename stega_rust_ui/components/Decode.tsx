@@ -3,13 +3,14 @@ import { TabsContent } from "@/components/ui/tabs"
 import { Button } from "@/components/ui/button"
 import { Input } from "@/components/ui/input"
 import { uploadImage } from '@/utils/upload_api';
-import { decodeChunks } from '@/utils/decode_api';
+import { describeProgress, runJob } from '@/utils/job_api';
 import { Textarea } from './ui/textarea';
 
 const DecodeSection = () => {
   const [file, setFile] = useState<File | null>(null);
   const [chunk, setChunk] = useState<string>('');
   const [decodeResponse, setDecodeResponse] = useState<string | null>(null);
+  const [progress, setProgress] = useState<string | null>(null);
 
   const handleFileChange = (event: React.ChangeEvent<HTMLInputElement>) => {
     if (event.target.files) {
@@ -25,31 +26,30 @@ const DecodeSection = () => {
     if (file && chunk) {
       try {
         const response = await uploadImage(file);
-        let file_path = '';
-        // check if status is success then extract the image_path
+        let file_id = '';
+        // check if status is success then extract the image id
         if (response.status === 'success') {
-          if (typeof response.image_path === 'string') {
-            file_path = response.image_path;
-            const decodeResponse = await decodeChunks(file_path , chunk);
-            if (decodeResponse.status === 'success') {
-              const { chunks, message } = decodeResponse;
-              if (chunks && chunks.length > 0) {
-                setDecodeResponse(`Chunk: ${chunks[0]}\nMessage: ${message}`);
-              } else {
-                setDecodeResponse(`Message: ${message}`);
-              }
+          if (typeof response.id === 'string') {
+            file_id = response.id;
+            const job = await runJob('decode', { id: file_id, chunk_type: chunk },
+              (update) => setProgress(describeProgress(update.progress)));
+            setProgress(null);
+            if (job.state === 'succeeded') {
+              const messages: string[] = job.result?.messages ?? [];
+              setDecodeResponse(`Chunk: ${chunk}\nMessage: ${messages.join('\n')}`);
               alert('Image successfully decoded');
             } else {
-              setDecodeResponse(`Error: ${decodeResponse.message}`);
-              throw new Error(decodeResponse.message);
+              setDecodeResponse(`Error: ${job.message ?? job.state}`);
+              throw new Error(job.message ?? job.state);
             }
           } else {
-            throw new Error('Invalid image path received');
+            throw new Error('Invalid image id received');
           }
         } else {
           throw new Error(`Error uploading image: ${response.message}`);
         }
       } catch (error) {
+        setProgress(null);
         if (error instanceof Error) {
           setDecodeResponse(`Error: ${error.message}`);
         } else {
@@ -70,6 +70,7 @@ const DecodeSection = () => {
         <Input type="file" accept="image/*" onChange={handleFileChange} />
         <Textarea placeholder="Enter the chunk to Decode" value={chunk} onChange={handleTextareaChange} />
         <Button onClick={handleDecodeClick}>Decode</Button>
+        {progress && <p className="text-sm text-gray-600">{progress}</p>}
         {decodeResponse && (
           <div className="mt-4 p-4 border rounded bg-gray-100">
             <h3 className="text-lg font-semibold">Decoded Response:</h3>
@@ -4,7 +4,7 @@ import { Button } from "@/components/ui/button"
 import { Input } from "@/components/ui/input"
 import { uploadImage } from '@/utils/upload_api';
 import { Textarea } from './ui/textarea';
-import { encodeChunks } from '@/utils/encode_api';
+import { describeProgress, runJob } from '@/utils/job_api';
 import { downloadFile } from '@/utils/download_api';
 
 const EncodeSection = () => {
@@ -12,6 +12,7 @@ const EncodeSection = () => {
   const [chunk, setChunk] = useState<string>('');
   const [message, setMessage] = useState<string>('');
   const [encodeResponse, setEncodeResponse] = useState<string | null>(null);
+  const [progress, setProgress] = useState<string | null>(null);
 
   const handleFileChange = (event: React.ChangeEvent<HTMLInputElement>) => {
     if (event.target.files) {
@@ -31,28 +32,31 @@ const EncodeSection = () => {
     if (file && chunk && message) {
       try {
         const response = await uploadImage(file);
-        let file_path = '';
-        // check if status is success then extract the image_path
+        let file_id = '';
+        // check if status is success then extract the image id
         if (response.status === 'success') {
-          if (typeof response.image_path === 'string') {
-            file_path = response.image_path;
-            const encodeResponse = await encodeChunks(file_path, chunk, message);
-            if (encodeResponse.status === 'success') {
-              setEncodeResponse(encodeResponse.message);
+          if (typeof response.id === 'string') {
+            file_id = response.id;
+            const job = await runJob('encode', { id: file_id, chunk_type: chunk, message },
+              (update) => setProgress(describeProgress(update.progress)));
+            setProgress(null);
+            if (job.state === 'succeeded') {
+              setEncodeResponse('Message encoded');
               alert('Data encoded successfully');
-              console.log('Encode Response:', encodeResponse);
-              downloadFile(file.name);
+              console.log('Encode Job:', job);
+              downloadFile(job.result?.id ?? file_id, file.name);
             }
             else {
-              setEncodeResponse(`Error: ${encodeResponse.message}`);
+              setEncodeResponse(`Error: ${job.message ?? job.state}`);
             }
           } else {
-            throw new Error('Invalid image path received');
+            throw new Error('Invalid image id received');
           }
         } else {
           throw new Error(`Error uploading image: ${response.message}`);
         }
       } catch (error) {
+        setProgress(null);
         if (error instanceof Error) {
           setEncodeResponse(`Error: ${error.message}`);
         }
@@ -73,6 +77,7 @@ const EncodeSection = () => {
         <Textarea placeholder="Enter your Chunk" onChange={handleTextareaChangeChunk} />
         <Textarea placeholder="Enter your secret message here" onChange={handleTextareaChangeMesssage} />
         <Button onClick={handleEncodeClick}>Encode and Download Image</Button>
+        {progress && <p className="text-sm text-gray-600">{progress}</p>}
         {encodeResponse && (
           <div className="mt-4 p-4 border rounded bg-gray-100">
             <h3 className="text-lg font-semibold">Encode Response:</h3>
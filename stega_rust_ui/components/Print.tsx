@@ -19,12 +19,12 @@ const PrintSection = () => {
     if (file) {
       try {
         const response = await uploadImage(file);
-        let file_path = '';
-        // check if status is success then extract the image_path
+        let file_id = '';
+        // check if status is success then extract the image id
         if (response.status === 'success') {
-          if (typeof response.image_path === 'string') {
-            file_path = response.image_path;
-            const printResponse = await printChunks(file_path);
+          if (typeof response.id === 'string') {
+            file_id = response.id;
+            const printResponse = await printChunks(file_id);
             if (printResponse.status === 'success') {
               const { chunks } = printResponse;
               if (chunks && chunks.length > 0) {
@@ -38,7 +38,7 @@ const PrintSection = () => {
               setPrintResponse(`Error: ${printResponse.message}`);
             }
           } else {
-            throw new Error('Invalid image path received');
+            throw new Error('Invalid image id received');
           }
         } else {
           throw new Error(`Error printing hidden chunks: ${response.message}`);
@@ -26,12 +26,12 @@ const RemoveSection = () => {
     if (file && chunk) {
       try {
         const response = await uploadImage(file);
-        let file_path = '';
-        // check if status is success then extract the image_path
+        let file_id = '';
+        // check if status is success then extract the image id
         if (response.status === 'success') {
-          if (typeof response.image_path === 'string') {
-            file_path = response.image_path;
-            const removeResponse = await removeChunk(file_path , chunk);
+          if (typeof response.id === 'string') {
+            file_id = response.id;
+            const removeResponse = await removeChunk(file_id, chunk);
             if (removeResponse.status === 'success') {
               const { chunks, message } = removeResponse;
               if (chunks && chunks.length > 0) {
@@ -39,14 +39,14 @@ const RemoveSection = () => {
               } else {
                 setRemoveResponse(`Message: ${message}`);
               }
-              downloadFile(file.name);
+              downloadFile(removeResponse.id ?? file_id, file.name);
               alert('Hidden data removed successfully');
             } else {
               setRemoveResponse(`Error: ${removeResponse.message}`);
               throw new Error(removeResponse.message);
             }
           } else {
-            throw new Error('Invalid image path received');
+            throw new Error('Invalid image id received');
           }
         } else {
           throw new Error(`Error uploading image: ${response.message}`);
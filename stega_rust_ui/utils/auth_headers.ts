@@ -0,0 +1,6 @@
+// Bearer token for the pngme server, set through NEXT_PUBLIC_STEGARUST_TOKEN.
+export const authHeaders = (): Record<string, string> => {
+  const token = process.env.NEXT_PUBLIC_STEGARUST_TOKEN;
+
+  return token ? { Authorization: `Bearer ${token}` } : {};
+};
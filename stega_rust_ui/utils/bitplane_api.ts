@@ -0,0 +1,24 @@
+import axios from 'axios';
+import { authHeaders } from './auth_headers';
+
+// This function fetches a rendered bit plane and returns an object URL for an <img>.
+export const fetchBitPlane = async (
+  id: string,
+  channel: number,
+  bit: number,
+  diff?: string
+): Promise<string | null> => {
+  try {
+    const response = await axios.get('https://stegarust.onrender.com/bitplane', {
+      params: { id, channel, bit, diff },
+      headers: authHeaders(),
+      responseType: 'blob'
+    });
+
+    return window.URL.createObjectURL(response.data);
+  } catch (error) {
+    console.error('Error rendering bit plane:', error);
+
+    return null;
+  }
+};
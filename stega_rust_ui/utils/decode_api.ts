@@ -1,4 +1,5 @@
 import axios from 'axios';
+import { authHeaders } from './auth_headers';
 
 interface PrintResponse {
   status: string;
@@ -6,12 +7,12 @@ interface PrintResponse {
   message: string;
 }
 
-export const decodeChunks = async (path: string, chunk_type: String): Promise<PrintResponse> => {
+export const decodeChunks = async (id: string, chunk_type: String): Promise<PrintResponse> => {
   try {
     const response = await axios.post('https://stegarust.onrender.com/decode', {
-      path,
+      id,
       chunk_type
-    });
+    }, { headers: authHeaders() });
 
     return response.data;
   } catch (error) {
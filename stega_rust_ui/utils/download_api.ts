@@ -1,11 +1,12 @@
 import axios from 'axios';
+import { authHeaders } from './auth_headers';
 
 // This function downloads a file from the server.
-export const downloadFile = async (fileName: string) => {
+export const downloadFile = async (id: string, fileName: string) => {
     try {
-        console.log(fileName)
-        const response = await axios.get(`https://stegarust.onrender.com/download/${fileName}`, {
-            responseType: 'blob'
+        const response = await axios.get(`https://stegarust.onrender.com/download/${id}`, {
+            responseType: 'blob',
+            headers: authHeaders()
         });
         const url = window.URL.createObjectURL(new Blob([response.data]));
         const link = document.createElement('a');
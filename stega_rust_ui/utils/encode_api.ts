@@ -1,18 +1,20 @@
 import axios from 'axios';
+import { authHeaders } from './auth_headers';
 
 interface PrintResponse {
   status: string;
   chunks: string[] | null;
+  id?: string | null;
   message: string;
 }
 
-export const encodeChunks = async (path: string, chunk_type: String, message: String): Promise<PrintResponse> => {
+export const encodeChunks = async (id: string, chunk_type: String, message: String): Promise<PrintResponse> => {
   try {
     const response = await axios.post('https://stegarust.onrender.com/encode', {
-      path,
+      id,
       chunk_type,
       message
-    });
+    }, { headers: authHeaders() });
 
     return response.data;
   } catch (error) {
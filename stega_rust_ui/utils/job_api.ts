@@ -0,0 +1,100 @@
+import { authHeaders } from './auth_headers';
+
+const API = 'https://stegarust.onrender.com';
+
+export interface JobProgress {
+  stage: 'parsing' | 'embedding' | 'extracting' | 'analyzing';
+  bytes_parsed?: number;
+  bytes_total?: number;
+  chunks_processed?: number;
+  bits_embedded?: number;
+  bits_extracted?: number;
+  bits_total?: number;
+  channels_analyzed?: number;
+  channels_total?: number;
+}
+
+export interface Job {
+  id: string;
+  kind: string;
+  state: 'queued' | 'running' | 'succeeded' | 'failed' | 'cancelled';
+  progress?: JobProgress;
+  result?: any;
+  code?: string;
+  message?: string;
+}
+
+// Describes a progress report in a short line such as "Parsing: 40% (3 chunks)".
+export const describeProgress = (progress?: JobProgress): string => {
+  if (!progress) {
+    return 'Waiting to start';
+  }
+  const percent = (done?: number, total?: number) =>
+    total ? `${Math.floor(((done ?? 0) * 100) / total)}%` : '';
+
+  switch (progress.stage) {
+    case 'parsing':
+      return `Parsing: ${percent(progress.bytes_parsed, progress.bytes_total)} (${progress.chunks_processed} chunks)`;
+    case 'embedding':
+      return `Embedding: ${percent(progress.bits_embedded, progress.bits_total)}`;
+    case 'extracting':
+      return `Extracting: ${percent(progress.bits_extracted, progress.bits_total)}`;
+    case 'analyzing':
+      return `Analyzing: ${progress.channels_analyzed} of ${progress.channels_total} channels`;
+  }
+};
+
+// This function queues a job and follows its event stream until it finishes,
+// calling onProgress on every update. fetch is used instead of EventSource
+// because EventSource cannot send the Authorization header.
+export const runJob = async (
+  kind: string,
+  body: object,
+  onProgress: (job: Job) => void
+): Promise<Job> => {
+  const submitted = await fetch(`${API}/jobs/${kind}`, {
+    method: 'POST',
+    headers: { 'Content-Type': 'application/json', ...authHeaders() },
+    body: JSON.stringify(body)
+  });
+  const queued = await submitted.json();
+  if (!submitted.ok || !queued.job) {
+    throw new Error(queued.message ?? 'Could not queue the job');
+  }
+
+  const events = await fetch(`${API}/jobs/${queued.job.id}/events`, {
+    headers: authHeaders()
+  });
+  if (!events.ok || !events.body) {
+    throw new Error('Could not follow the job');
+  }
+
+  const reader = events.body.pipeThrough(new TextDecoderStream()).getReader();
+  let buffer = '';
+  for (;;) {
+    const { value, done } = await reader.read();
+    if (done) {
+      throw new Error('The job stream ended early');
+    }
+    buffer += value;
+    let end;
+    while ((end = buffer.indexOf('\n\n')) !== -1) {
+      const event = buffer.slice(0, end);
+      buffer = buffer.slice(end + 2);
+      const data = event
+        .split('\n')
+        .filter((line) => line.startsWith('data:'))
+        .map((line) => line.slice(5))
+        .join('\n');
+      if (!data) {
+        continue;
+      }
+      const job: Job = JSON.parse(data);
+      onProgress(job);
+      if (event.includes('event:done')) {
+        reader.cancel();
+        return job;
+      }
+    }
+  }
+};
@@ -1,4 +1,5 @@
 import axios from 'axios';
+import { authHeaders } from './auth_headers';
 
 interface PrintResponse {
   status: string;
@@ -6,11 +7,11 @@ interface PrintResponse {
   message: string;
 }
 
-export const printChunks = async (path: string): Promise<PrintResponse> => {
+export const printChunks = async (id: string): Promise<PrintResponse> => {
   try {
     const response = await axios.post('https://stegarust.onrender.com/print', {
-      path
-    });
+      id
+    }, { headers: authHeaders() });
 
     return response.data;
   } catch (error) {
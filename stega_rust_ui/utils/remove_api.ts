@@ -1,17 +1,19 @@
 import axios from 'axios';
+import { authHeaders } from './auth_headers';
 
 interface PrintResponse {
   status: string;
   chunks: string[] | null;
+  id?: string | null;
   message: string;
 }
 
-export const removeChunk = async (path: string, chunk_type: string): Promise<PrintResponse> => {
+export const removeChunk = async (id: string, chunk_type: string): Promise<PrintResponse> => {
   try {
     const response = await axios.post('https://stegarust.onrender.com/remove', {
-      path,
+      id,
       chunk_type
-    });
+    }, { headers: authHeaders() });
 
     console.log
 
@@ -1,8 +1,9 @@
 import axios from 'axios';
+import { authHeaders } from './auth_headers';
 
 interface UploadResponse {
   status: string;
-  image_path: string[] | null;
+  id: string | null;
   message: string;
 }
 
@@ -13,6 +14,7 @@ export const uploadImage = async (file: File): Promise<UploadResponse> => {
 
     const response = await axios.post('https://stegarust.onrender.com/upload', formData, {
       headers: {
+        ...authHeaders(),
         'Content-Type': 'multipart/form-data'
       }
     });
@@ -24,7 +26,7 @@ export const uploadImage = async (file: File): Promise<UploadResponse> => {
 
     return {
       status: 'error',
-      image_path: null,
+      id: null,
       message: 'An error occurred while uploading the image'
     };
   }